@@ -12,6 +12,11 @@ pub type Alignment = Vec<AlignmentCell>;
 
 pub type Alignments = (f32, Vec<Alignment>);
 
+// The most alignments that aligners return. Repetitive text can have
+// enormous numbers of equally good alignments, and callers rarely need more
+// than a few of them.
+pub const MAX_ALIGNMENTS: usize = 100;
+
 // Calculate the best alignments of sequences `a` and `b`.
 //
 // Arguments:
@@ -28,4 +33,15 @@ pub type Alignments = (f32, Vec<Alignment>);
 //     alignments. The first index in each pair is an index into `a` and the
 //     second is into `b`. Either (but not both) indices in a pair may be `None`
 //     indicating a gap in the corresponding sequence.
-pub type Align = Fn(&str, &str, &ScoringFunction, &GapPenaltyFunction) -> Alignments;
\ No newline at end of file
+pub type Align = Fn(&str, &str, &ScoringFunction, &GapPenaltyFunction) -> Alignments;
+
+// The penalties for opening a gap and for extending it by one element, as
+// given by `gap_penalty`.
+//
+// Aligners use affine gaps, so `gap_penalty` is assumed to grow linearly with
+// the gap length after the first element. Extending a gap never costs more
+// than opening a new one next to it.
+pub fn gap_costs(gap_penalty: &GapPenaltyFunction) -> (f32, f32) {
+    let open = gap_penalty(1);
+    (open, (gap_penalty(2) - open).min(open))
+}
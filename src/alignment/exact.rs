@@ -0,0 +1,104 @@
+// A fast path for text that hasn't changed.
+//
+// If `a` occurs unchanged in `b`, each occurrence is aligned element for
+// element without building any score matrix. Otherwise this falls back to
+// local alignment (see `linear_space`).
+
+use alignment::align::*;
+use alignment::linear_space;
+
+pub fn align(
+    a: &str,
+    b: &str,
+    score_func: &ScoringFunction,
+    gap_penalty: &GapPenaltyFunction,
+) -> (f32, Vec<Alignment>) {
+    align_with_limit(a, b, score_func, gap_penalty, MAX_ALIGNMENTS)
+}
+
+// Like `align`, but returning at most `limit` alignments.
+pub fn align_with_limit(
+    a: &str,
+    b: &str,
+    score_func: &ScoringFunction,
+    gap_penalty: &GapPenaltyFunction,
+    limit: usize,
+) -> (f32, Vec<Alignment>) {
+    let offsets = occurrences(a, b, limit);
+    if offsets.is_empty() {
+        return linear_space::align_with_limit(a, b, score_func, gap_penalty, limit);
+    }
+
+    let a_len = a.chars().count();
+    let score = a.chars().map(|c| score_func(c, c)).sum();
+    let alignments = offsets
+        .into_iter()
+        .map(|offset| {
+            (0..a_len)
+                .map(|index| AlignmentCell::Both {
+                    left: index,
+                    right: offset + index,
+                })
+                .collect()
+        })
+        .collect();
+
+    (score, alignments)
+}
+
+// The character offsets of up to `limit` occurrences of `a` in `b`,
+// including overlapping ones.
+fn occurrences(a: &str, b: &str, limit: usize) -> Vec<usize> {
+    let mut offsets = vec![];
+    if a.is_empty() {
+        return offsets;
+    }
+
+    // Byte and character positions of the last occurrence, so that characters
+    // are only counted once.
+    let (mut byte_pos, mut char_pos) = (0, 0);
+    let mut search_from = 0;
+
+    while offsets.len() < limit {
+        let found = match b[search_from..].find(a) {
+            Some(found) => search_from + found,
+            None => break,
+        };
+
+        char_pos += b[byte_pos..found].chars().count();
+        byte_pos = found;
+        offsets.push(char_pos);
+
+        let first_char = b[found..].chars().next().map_or(1, char::len_utf8);
+        search_from = found + first_char;
+    }
+
+    offsets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scoring::*;
+
+    #[test]
+    fn unchanged_text_is_found() {
+        let (score, alignments) = align("äbc", "xäbcäbcäbc", &score_func, &gap_penalty);
+        assert_eq!(score, 9.0);
+        assert_eq!(alignments.len(), 3);
+        assert_eq!(alignments[1][0], AlignmentCell::Both { left: 0, right: 4 });
+    }
+
+    #[test]
+    fn overlapping_occurrences() {
+        assert_eq!(occurrences("aa", "aaaa", 10), vec![0, 1, 2]);
+        assert_eq!(occurrences("aa", "aaaa", 2), vec![0, 1]);
+    }
+
+    #[test]
+    fn changed_text_falls_back_to_local_alignment() {
+        let (score, alignments) = align("abcd", "xxabXdxx", &score_func, &gap_penalty);
+        assert_eq!(score, 6.0);
+        assert_eq!(alignments[0][0], AlignmentCell::Both { left: 0, right: 2 });
+    }
+}
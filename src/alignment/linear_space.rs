@@ -0,0 +1,301 @@
+// Local alignment in linear space.
+//
+// This finds the same alignments as `smith_waterman::align` without building
+// the full score and traceback matrices, so memory use grows with the length
+// of the sequences rather than the product of their lengths:
+//
+//   1. A forward pass keeps only two rows of the Smith-Waterman matrix and
+//      records where the best local alignments end.
+//   2. For each end, a reverse pass from that end finds where the alignment
+//      starts.
+//   3. The text between start and end is then globally aligned in linear
+//      space. See `needleman_wunsch::myers_miller`.
+
+use alignment::align::*;
+use alignment::needleman_wunsch::{max3, myers_miller, Gaps, DEAD};
+
+// Scores can be fractional, in which case forward and reverse sums may
+// round differently.
+const TOLERANCE: f32 = 1e-4;
+
+fn approx_eq(x: f32, y: f32) -> bool {
+    (x - y).abs() <= TOLERANCE * y.abs().max(1.0)
+}
+
+// Find the best local alignment score and every (row, col) index in the
+// score matrix where it's reached, in row-major order.
+fn best_ends(
+    a: &[char],
+    b: &[char],
+    score_func: &ScoringFunction,
+    gaps: Gaps,
+) -> (f32, Vec<(usize, usize)>) {
+    let mut prev = vec![0.0; b.len() + 1];
+    let mut curr = vec![0.0; b.len() + 1];
+
+    // The best scores of alignments ending in a gap in `b` in each column.
+    let mut up = vec![DEAD; b.len() + 1];
+
+    let mut max_score = 0.0;
+    let mut ends = vec![];
+
+    for row in 1..=a.len() {
+        curr[0] = 0.0;
+        let mut left = DEAD;
+        for col in 1..=b.len() {
+            up[col] = (prev[col] - gaps.open).max(up[col] - gaps.extend);
+            left = (curr[col - 1] - gaps.open).max(left - gaps.extend);
+
+            let score = max3(
+                prev[col - 1] + score_func(a[row - 1], b[col - 1]),
+                up[col],
+                left,
+            );
+
+            curr[col] = if score > 0.0 { score } else { 0.0 };
+
+            if curr[col] > max_score {
+                max_score = curr[col];
+                ends.clear();
+                ends.push((row, col));
+            } else if curr[col] > 0.0 && curr[col] == max_score {
+                ends.push((row, col));
+            }
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    (max_score, ends)
+}
+
+// Find where the best local alignment ending at `a.len(), b.len()` starts.
+//
+// This aligns the reversed sequences starting from their beginnings. Every
+// suffix of an optimal local alignment scores at least zero, so anything
+// going negative is abandoned. (A suffix cut part way through a gap is
+// charged for opening it, so gaps are allowed to go a little negative.) Of the
+// possible starts, the one closest to the end is used, matching where a
+// Smith-Waterman traceback stops.
+fn best_start(
+    a: &[char],
+    b: &[char],
+    score_func: &ScoringFunction,
+    gaps: Gaps,
+    target: f32,
+) -> (usize, usize) {
+    let gap_slack = (gaps.open - gaps.extend).abs() + TOLERANCE;
+    let prune = |score: f32, slack: f32| if score < -slack { DEAD } else { score };
+
+    // Columns walk backward through `b`; rows backward through `a`. `prev`
+    // and `curr` hold the best scores, and `left` the best scores ending in
+    // a gap in `a`.
+    let mut prev = vec![DEAD; a.len() + 1];
+    let mut curr = vec![DEAD; a.len() + 1];
+    let mut left = vec![DEAD; a.len() + 1];
+
+    prev[0] = 0.0;
+    let mut up = DEAD;
+    for x in 1..=a.len() {
+        up = prune((prev[x - 1] - gaps.open).max(up - gaps.extend), gap_slack);
+        prev[x] = up;
+    }
+
+    let mut best: Option<(usize, usize)> = None;
+
+    for y in 1..=b.len() {
+        if let Some((bx, by)) = best {
+            if y > bx + by {
+                break;
+            }
+        }
+
+        let b_char = b[b.len() - y];
+
+        left[0] = prune((prev[0] - gaps.open).max(left[0] - gaps.extend), gap_slack);
+        curr[0] = left[0];
+
+        let mut up = DEAD;
+        for x in 1..=a.len() {
+            up = prune((curr[x - 1] - gaps.open).max(up - gaps.extend), gap_slack);
+            left[x] = prune((prev[x] - gaps.open).max(left[x] - gaps.extend), gap_slack);
+            let both = prune(prev[x - 1] + score_func(a[a.len() - x], b_char), TOLERANCE);
+            curr[x] = max3(both, up, left[x]);
+
+            // Optimal local alignments start by aligning both sequences.
+            if approx_eq(both, target) {
+                match best {
+                    Some((bx, by)) if bx + by <= x + y => {}
+                    _ => best = Some((x, y)),
+                }
+            }
+        }
+
+        if curr.iter().all(|s| *s == DEAD) {
+            break;
+        }
+
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    let (x, y) = best.expect("reverse pass did not reach the forward score");
+    (a.len() - x, b.len() - y)
+}
+
+pub fn align(
+    a: &str,
+    b: &str,
+    score_func: &ScoringFunction,
+    gap_penalty: &GapPenaltyFunction,
+) -> (f32, Vec<Alignment>) {
+    align_with_limit(a, b, score_func, gap_penalty, MAX_ALIGNMENTS)
+}
+
+// Like `align`, but returning at most `limit` alignments.
+pub fn align_with_limit(
+    a: &str,
+    b: &str,
+    score_func: &ScoringFunction,
+    gap_penalty: &GapPenaltyFunction,
+    limit: usize,
+) -> (f32, Vec<Alignment>) {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    let gaps = Gaps::new(gap_penalty);
+
+    let (max_score, ends) = best_ends(&a, &b, score_func, gaps);
+    if ends.is_empty() {
+        return (0.0, vec![Alignment::new()]);
+    }
+
+    let mut alignments = vec![];
+    for (row, col) in ends.into_iter().take(limit) {
+        let (start_row, start_col) = best_start(&a[..row], &b[..col], score_func, gaps, max_score);
+
+        let mut alignment = Alignment::new();
+        myers_miller(
+            &a[start_row..row],
+            &b[start_col..col],
+            (start_row, start_col),
+            score_func,
+            gaps,
+            (gaps.start(), gaps.start()),
+            &mut alignment,
+        );
+        alignments.push(alignment);
+    }
+
+    (max_score, alignments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alignment::smith_waterman;
+    use scoring::*;
+
+    const INPUT1: &str = "GGTTGACTA";
+    const INPUT2: &str = "TGTTACGG";
+
+    // Score an alignment the same way the aligner does.
+    fn score_of(alignment: &Alignment, a: &str, b: &str) -> f32 {
+        let a: Vec<char> = a.chars().collect();
+        let b: Vec<char> = b.chars().collect();
+        let (open, extend) = gap_costs(&gap_penalty);
+
+        let mut score = 0.0;
+        let mut previous = None;
+        for cell in alignment {
+            score += match cell {
+                AlignmentCell::Both { left, right } => score_func(a[*left], b[*right]),
+                AlignmentCell::RightGap { .. } if previous == Some("right") => -extend,
+                AlignmentCell::LeftGap { .. } if previous == Some("left") => -extend,
+                _ => -open,
+            };
+            previous = match cell {
+                AlignmentCell::Both { .. } => None,
+                AlignmentCell::RightGap { .. } => Some("right"),
+                AlignmentCell::LeftGap { .. } => Some("left"),
+            };
+        }
+        score
+    }
+
+    #[test]
+    fn canned_alignment() {
+        let (max_score, alignments) = align(INPUT1, INPUT2, &score_func, &gap_penalty);
+        assert_eq!(max_score, 13.0);
+        assert_eq!(alignments.len(), 1);
+
+        let expected = vec![
+            AlignmentCell::Both { left: 1, right: 1 },
+            AlignmentCell::Both { left: 2, right: 2 },
+            AlignmentCell::Both { left: 3, right: 3 },
+            AlignmentCell::RightGap { left: 4 },
+            AlignmentCell::Both { left: 5, right: 4 },
+            AlignmentCell::Both { left: 6, right: 5 },
+        ];
+
+        assert_eq!(alignments[0], expected);
+    }
+
+    #[test]
+    fn no_matches() {
+        let (max_score, alignments) = align("aaa", "bbb", &score_func, &gap_penalty);
+        assert_eq!(max_score, 0.0);
+        assert_eq!(alignments, vec![Alignment::new()]);
+    }
+
+    #[test]
+    fn agrees_with_smith_waterman() {
+        let cases = [
+            (INPUT1, INPUT2),
+            ("asdf", "qwer\nasdf"),
+            ("def func():\n    x = 1", "import os\n\ndef func():\n    x = 2\n"),
+            ("    y = 2\n", "def func():\n    x = 1\n    yy = 2\n    z = 3\n"),
+            ("ACACACTA", "AGCACACA"),
+            ("héllo wörld", "hello world, héllo wörld!"),
+            ("abcdefghij", "abcdeXXXXXXXXXXXXXXXXXXXXfghij"),
+            ("abcdeXXXXXXXXXXXXXXXXXXXXfghij", "--abcdefghij--"),
+            ("def f():\n    a = 1\n    b = 2\n", "def f():\n    a = 1\n    q = 0\n    r = 0\n    b = 2\n"),
+        ];
+
+        for (a, b) in cases.iter() {
+            let (expected_score, expected) = smith_waterman::align(a, b, &score_func, &gap_penalty);
+            let (score, alignments) = align(a, b, &score_func, &gap_penalty);
+
+            assert_eq!(score, expected_score, "{:?} vs {:?}", a, b);
+            for alignment in &alignments {
+                assert_eq!(score_of(alignment, a, b), score);
+                assert!(expected.contains(alignment), "{:?} vs {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn alignments_are_limited() {
+        let a = " ".repeat(20);
+        let b = " ".repeat(200);
+
+        let (max_score, alignments) = align_with_limit(&a, &b, &score_func, &gap_penalty, 5);
+        assert_eq!(max_score, 60.0);
+        assert_eq!(alignments.len(), 5);
+        assert_eq!(alignments[0][0], AlignmentCell::Both { left: 0, right: 0 });
+    }
+
+    #[test]
+    fn large_inputs() {
+        let topic = "fn anchored() { return 42; }";
+        let mut text: String = (0..20000).map(|n| format!("let x{} = {};\n", n, n)).collect();
+        let offset = text.len() / 2;
+        text.insert_str(offset, topic);
+
+        let (score, alignments) = align(topic, &text, &score_func, &gap_penalty);
+        assert_eq!(score, 3.0 * topic.len() as f32);
+        assert_eq!(alignments.len(), 1);
+        assert_eq!(
+            alignments[0][0],
+            AlignmentCell::Both { left: 0, right: offset }
+        );
+    }
+}
@@ -1,2 +1,8 @@
 pub mod align;
+pub mod exact;
+pub mod linear_space;
+pub mod needleman_wunsch;
+pub mod registry;
+pub mod semi_global;
 pub mod smith_waterman;
+pub mod tokens;
@@ -0,0 +1,334 @@
+// Global alignment with affine gaps (Needleman-Wunsch, as extended by
+// Gotoh).
+//
+// Both sequences are aligned from end to end, so this suits comparing two
+// versions of the same text rather than finding one inside the other. The
+// alignment is found in linear space with the Myers-Miller algorithm, which
+// the other aligners also use once they've found what to align.
+
+use alignment::align::*;
+
+pub(crate) const DEAD: f32 = f32::NEG_INFINITY;
+
+pub(crate) fn max3(x: f32, y: f32, z: f32) -> f32 {
+    x.max(y).max(z)
+}
+
+// The affine gap penalties shared by every pass.
+#[derive(Clone, Copy)]
+pub(crate) struct Gaps {
+    pub open: f32,
+    pub extend: f32,
+}
+
+impl Gaps {
+    pub fn new(gap_penalty: &GapPenaltyFunction) -> Gaps {
+        let (open, extend) = gap_costs(gap_penalty);
+        Gaps { open, extend }
+    }
+
+    // What a gap costs on top of the penalty for each of its elements.
+    pub fn start(&self) -> f32 {
+        self.open - self.extend
+    }
+}
+
+// The last row of the global alignment scores of `a` and `b`, both overall
+// (first) and for alignments ending in a gap in `b` (second).
+//
+// `first_gap_start` is the start penalty (see `Gaps::start`) of a gap in `b`
+// at the start of the alignment. It's zero when the gap continues one from
+// earlier on.
+pub(crate) fn global_scores(
+    a: &[char],
+    b: &[char],
+    score_func: &ScoringFunction,
+    gaps: Gaps,
+    first_gap_start: f32,
+) -> (Vec<f32>, Vec<f32>) {
+    let start = gaps.start();
+
+    let mut scores = vec![0.0; b.len() + 1];
+    let mut up = vec![0.0; b.len() + 1];
+
+    let mut penalty = start;
+    for col in 1..=b.len() {
+        penalty += gaps.extend;
+        scores[col] = -penalty;
+        up[col] = -(penalty + start);
+    }
+
+    let mut penalty = first_gap_start;
+    for row in 1..=a.len() {
+        let mut diag = scores[0];
+        penalty += gaps.extend;
+        let mut score = -penalty;
+        scores[0] = score;
+
+        let mut left = -(penalty + start);
+        for col in 1..=b.len() {
+            left = left.max(score - start) - gaps.extend;
+            up[col] = up[col].max(scores[col] - start) - gaps.extend;
+            score = max3(up[col], left, diag + score_func(a[row - 1], b[col - 1]));
+            diag = scores[col];
+            scores[col] = score;
+        }
+    }
+    up[0] = scores[0];
+
+    (scores, up)
+}
+
+// Globally align `a` and `b` in linear space with the Myers-Miller
+// algorithm, appending the cells to `alignment`. `offsets` are the positions
+// of `a` and `b` in the original sequences.
+//
+// `start_before` and `start_after` are the start penalties (see
+// `Gaps::start`) of gaps in `b` at the start and end of the alignment. They're
+// zero where the gap continues one in a neighbouring part of the alignment.
+pub(crate) fn myers_miller(
+    a: &[char],
+    b: &[char],
+    offsets: (usize, usize),
+    score_func: &ScoringFunction,
+    gaps: Gaps,
+    (start_before, start_after): (f32, f32),
+    alignment: &mut Alignment,
+) {
+    let left_gaps = |cols: std::ops::Range<usize>| {
+        cols.map(move |col| AlignmentCell::LeftGap {
+            right: offsets.1 + col,
+        })
+    };
+
+    if b.is_empty() {
+        alignment.extend((0..a.len()).map(|row| AlignmentCell::RightGap {
+            left: offsets.0 + row,
+        }));
+    } else if a.is_empty() {
+        alignment.extend(left_gaps(0..b.len()));
+    } else if a.len() == 1 {
+        let gap = |len: usize| {
+            if len == 0 {
+                0.0
+            } else {
+                gaps.open + (len - 1) as f32 * gaps.extend
+            }
+        };
+
+        // Either align the single element of `a` with some element of `b`...
+        let mut best_col = None;
+        let mut best_score = DEAD;
+        for (col, b_char) in b.iter().enumerate() {
+            let score = score_func(a[0], *b_char) - gap(col) - gap(b.len() - col - 1);
+            if score > best_score {
+                best_score = score;
+                best_col = Some(col);
+            }
+        }
+
+        // ...or put it in a gap of its own.
+        let gap_start = start_before.min(start_after);
+        if -(gap_start + gaps.extend) - gap(b.len()) > best_score {
+            best_col = None;
+        }
+
+        let gap_cell = AlignmentCell::RightGap { left: offsets.0 };
+        match best_col {
+            Some(col) => {
+                alignment.extend(left_gaps(0..col));
+                alignment.push(AlignmentCell::Both {
+                    left: offsets.0,
+                    right: offsets.1 + col,
+                });
+                alignment.extend(left_gaps(col + 1..b.len()));
+            }
+            None if start_before <= start_after => {
+                alignment.push(gap_cell);
+                alignment.extend(left_gaps(0..b.len()));
+            }
+            None => {
+                alignment.extend(left_gaps(0..b.len()));
+                alignment.push(gap_cell);
+            }
+        }
+    } else {
+        let mid = a.len() / 2;
+
+        let (upper, upper_gap) = global_scores(&a[..mid], b, score_func, gaps, start_before);
+
+        let a_rev: Vec<char> = a[mid..].iter().rev().cloned().collect();
+        let b_rev: Vec<char> = b.iter().rev().cloned().collect();
+        let (lower, lower_gap) = global_scores(&a_rev, &b_rev, score_func, gaps, start_after);
+
+        // Split `b` where the two halves together score best, either
+        // separately or joined by a gap crossing the middle of `a`. In the
+        // latter case both halves paid to open the gap.
+        let n = b.len();
+        let mut split = 0;
+        let mut through_gap = false;
+        let mut best_score = DEAD;
+        for col in 0..=n {
+            let separate = upper[col] + lower[n - col];
+            if separate > best_score {
+                best_score = separate;
+                split = col;
+                through_gap = false;
+            }
+
+            let joined = upper_gap[col] + lower_gap[n - col] + gaps.start();
+            if joined > best_score {
+                best_score = joined;
+                split = col;
+                through_gap = true;
+            }
+        }
+
+        if through_gap {
+            myers_miller(
+                &a[..mid - 1],
+                &b[..split],
+                offsets,
+                score_func,
+                gaps,
+                (start_before, 0.0),
+                alignment,
+            );
+            alignment.push(AlignmentCell::RightGap {
+                left: offsets.0 + mid - 1,
+            });
+            alignment.push(AlignmentCell::RightGap {
+                left: offsets.0 + mid,
+            });
+            myers_miller(
+                &a[mid + 1..],
+                &b[split..],
+                (offsets.0 + mid + 1, offsets.1 + split),
+                score_func,
+                gaps,
+                (0.0, start_after),
+                alignment,
+            );
+        } else {
+            myers_miller(
+                &a[..mid],
+                &b[..split],
+                offsets,
+                score_func,
+                gaps,
+                (start_before, gaps.start()),
+                alignment,
+            );
+            myers_miller(
+                &a[mid..],
+                &b[split..],
+                (offsets.0 + mid, offsets.1 + split),
+                score_func,
+                gaps,
+                (gaps.start(), start_after),
+                alignment,
+            );
+        }
+    }
+}
+
+pub fn align(
+    a: &str,
+    b: &str,
+    score_func: &ScoringFunction,
+    gap_penalty: &GapPenaltyFunction,
+) -> (f32, Vec<Alignment>) {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let gaps = Gaps::new(gap_penalty);
+
+    let (scores, _) = global_scores(&a, &b, score_func, gaps, gaps.start());
+
+    let mut alignment = Alignment::new();
+    myers_miller(
+        &a,
+        &b,
+        (0, 0),
+        score_func,
+        gaps,
+        (gaps.start(), gaps.start()),
+        &mut alignment,
+    );
+
+    (scores[b.len()], vec![alignment])
+}
+
+// Global alignment of `a` with the part of `b` that it covers, for finding a
+// context in a file.
+//
+// `align` charges for every element of `b` that isn't aligned with `a`, so
+// when `b` is much longer than `a` its score mostly measures the length of
+// `b`. This leaves out the gaps at either end of the alignment, and their
+// penalties, so that the score can be compared with that of a perfect match.
+pub fn align_covered(
+    a: &str,
+    b: &str,
+    score_func: &ScoringFunction,
+    gap_penalty: &GapPenaltyFunction,
+) -> (f32, Vec<Alignment>) {
+    let (mut score, mut alignments) = align(a, b, score_func, gap_penalty);
+
+    for alignment in &mut alignments {
+        let is_end_gap = |cell: &AlignmentCell| matches!(cell, AlignmentCell::LeftGap { .. });
+        let leading = alignment.iter().take_while(|cell| is_end_gap(cell)).count();
+        alignment.drain(..leading);
+        let trailing = alignment.iter().rev().take_while(|cell| is_end_gap(cell)).count();
+        alignment.truncate(alignment.len() - trailing);
+
+        score += gap_penalty(leading as u32) + gap_penalty(trailing as u32);
+    }
+
+    (score, alignments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scoring::*;
+
+    #[test]
+    fn aligns_everything() {
+        let (score, alignments) = align("xabcx", "abc", &score_func, &gap_penalty);
+        assert_eq!(score, 9.0 - 2.0 * gap_penalty(1));
+        assert_eq!(
+            alignments,
+            vec![vec![
+                AlignmentCell::RightGap { left: 0 },
+                AlignmentCell::Both { left: 1, right: 0 },
+                AlignmentCell::Both { left: 2, right: 1 },
+                AlignmentCell::Both { left: 3, right: 2 },
+                AlignmentCell::RightGap { left: 4 },
+            ]]
+        );
+    }
+
+    #[test]
+    fn gaps_in_the_middle_are_joined() {
+        let (score, alignments) = align("abcdef", "abXXXXXXcdef", &score_func, &gap_penalty);
+        assert_eq!(score, 18.0 - gap_penalty(6));
+        assert_eq!(alignments[0].len(), 12);
+    }
+
+    #[test]
+    fn covered_alignment_leaves_out_end_gaps() {
+        let (score, alignments) = align_covered("abc", "xxabcxxx", &score_func, &gap_penalty);
+        assert_eq!(score, 9.0);
+        assert_eq!(
+            alignments,
+            vec![vec![
+                AlignmentCell::Both { left: 0, right: 2 },
+                AlignmentCell::Both { left: 1, right: 3 },
+                AlignmentCell::Both { left: 2, right: 4 },
+            ]]
+        );
+
+        // Gaps inside the covered part are still charged for.
+        let (score, _) = align_covered("abcd", "xxabXcdxxx", &score_func, &gap_penalty);
+        assert_eq!(score, 12.0 - gap_penalty(1));
+    }
+}
@@ -0,0 +1,75 @@
+// Alignment algorithms by name, so that users can choose between them.
+
+use alignment::align::*;
+use alignment::{exact, linear_space, needleman_wunsch, semi_global, smith_waterman, tokens};
+
+pub type AlignFn = fn(&str, &str, &ScoringFunction, &GapPenaltyFunction) -> Alignments;
+
+// The algorithm used when none is chosen.
+pub const DEFAULT_ALGORITHM: &str = "local";
+
+// Each algorithm with its name and a short description.
+pub const ALGORITHMS: [(&str, AlignFn, &str); 6] = [
+    (
+        "local",
+        linear_space::align,
+        "Smith-Waterman local alignment in linear space",
+    ),
+    (
+        "smith-waterman",
+        smith_waterman::align,
+        "Smith-Waterman local alignment with full matrices",
+    ),
+    (
+        "semi-global",
+        semi_global::align,
+        "the whole context, anywhere in the file",
+    ),
+    (
+        "needleman-wunsch",
+        needleman_wunsch::align_covered,
+        "global alignment of the context with the part of the file it covers",
+    ),
+    (
+        "tokens",
+        tokens::align,
+        "local alignment of words and punctuation rather than characters",
+    ),
+    (
+        "exact",
+        exact::align,
+        "unchanged text only, falling back to local alignment",
+    ),
+];
+
+// Find the algorithm called `name`.
+pub fn by_name(name: &str) -> Result<AlignFn, String> {
+    ALGORITHMS
+        .iter()
+        .find(|(n, _, _)| *n == name)
+        .map(|(_, align, _)| *align)
+        .ok_or_else(|| {
+            let names: Vec<&str> = ALGORITHMS.iter().map(|(n, _, _)| *n).collect();
+            format!(
+                "Unknown alignment algorithm {} (expected one of: {})",
+                name,
+                names.join(", ")
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_algorithm_exists() {
+        assert!(by_name(DEFAULT_ALGORITHM).is_ok());
+    }
+
+    #[test]
+    fn unknown_algorithm() {
+        let err = by_name("bogus").unwrap_err();
+        assert!(err.contains("semi-global"));
+    }
+}
@@ -0,0 +1,168 @@
+// Semi-global alignment: all of `a` is aligned, but it may start and end
+// anywhere in `b`.
+//
+// This suits finding a whole anchor context in a file. Unlike local
+// alignment, the edges of the context are never dropped just because they've
+// changed, at the cost of dragging in unrelated text when they've gone
+// altogether.
+//
+// A single pass keeps two rows of the score matrix, along with the column in
+// the first row where the best path to each cell began. Each best-scoring
+// region of `b` is then globally aligned with `a`.
+
+use alignment::align::*;
+use alignment::needleman_wunsch::{myers_miller, Gaps, DEAD};
+
+// The best of several (score, origin) candidates. Ties go to the latest
+// origin, i.e. the shortest alignment.
+fn best_of(candidates: &[(f32, usize)]) -> (f32, usize) {
+    candidates.iter().fold((DEAD, 0), |best, &(score, origin)| {
+        if score > best.0 || (score == best.0 && origin > best.1) {
+            (score, origin)
+        } else {
+            best
+        }
+    })
+}
+
+// Find the best semi-global alignment score and, for each column where it's
+// reached in the last row, the column where the alignment begins.
+fn best_regions(
+    a: &[char],
+    b: &[char],
+    score_func: &ScoringFunction,
+    gaps: Gaps,
+) -> (f32, Vec<(usize, usize)>) {
+    // Alignments may start anywhere in `b` for free.
+    let mut prev: Vec<(f32, usize)> = (0..=b.len()).map(|col| (0.0, col)).collect();
+    let mut curr = prev.clone();
+
+    // The best alignments ending in a gap in `b` in each column.
+    let mut up: Vec<(f32, usize)> = vec![(DEAD, 0); b.len() + 1];
+
+    for row in 1..=a.len() {
+        up[0] = best_of(&[
+            (prev[0].0 - gaps.open, 0),
+            (up[0].0 - gaps.extend, 0),
+        ]);
+        curr[0] = up[0];
+
+        let mut left = (DEAD, 0);
+        for col in 1..=b.len() {
+            up[col] = best_of(&[
+                (prev[col].0 - gaps.open, prev[col].1),
+                (up[col].0 - gaps.extend, up[col].1),
+            ]);
+            left = best_of(&[
+                (curr[col - 1].0 - gaps.open, curr[col - 1].1),
+                (left.0 - gaps.extend, left.1),
+            ]);
+            curr[col] = best_of(&[
+                (
+                    prev[col - 1].0 + score_func(a[row - 1], b[col - 1]),
+                    prev[col - 1].1,
+                ),
+                up[col],
+                left,
+            ]);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    // Alignments may also end anywhere in `b` for free.
+    let max_score = prev.iter().map(|cell| cell.0).fold(DEAD, f32::max);
+    let regions = prev
+        .iter()
+        .enumerate()
+        .filter(|(_, cell)| cell.0 == max_score)
+        .map(|(col, cell)| (cell.1, col))
+        .collect();
+
+    (max_score, regions)
+}
+
+pub fn align(
+    a: &str,
+    b: &str,
+    score_func: &ScoringFunction,
+    gap_penalty: &GapPenaltyFunction,
+) -> (f32, Vec<Alignment>) {
+    align_with_limit(a, b, score_func, gap_penalty, MAX_ALIGNMENTS)
+}
+
+// Like `align`, but returning at most `limit` alignments.
+pub fn align_with_limit(
+    a: &str,
+    b: &str,
+    score_func: &ScoringFunction,
+    gap_penalty: &GapPenaltyFunction,
+    limit: usize,
+) -> (f32, Vec<Alignment>) {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let gaps = Gaps::new(gap_penalty);
+
+    if a.is_empty() {
+        return (0.0, vec![Alignment::new()]);
+    }
+
+    let (max_score, regions) = best_regions(&a, &b, score_func, gaps);
+
+    let alignments = regions
+        .into_iter()
+        .take(limit)
+        .map(|(start, end)| {
+            let mut alignment = Alignment::new();
+            myers_miller(
+                &a,
+                &b[start..end],
+                (0, start),
+                score_func,
+                gaps,
+                (gaps.start(), gaps.start()),
+                &mut alignment,
+            );
+            alignment
+        })
+        .collect();
+
+    (max_score, alignments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scoring::*;
+
+    #[test]
+    fn context_is_fully_aligned() {
+        let (score, alignments) = align("Xbcd", "aabcdee", &score_func, &gap_penalty);
+        assert_eq!(score, 9.0 - gap_penalty(1));
+        assert_eq!(
+            alignments,
+            vec![vec![
+                AlignmentCell::RightGap { left: 0 },
+                AlignmentCell::Both { left: 1, right: 2 },
+                AlignmentCell::Both { left: 2, right: 3 },
+                AlignmentCell::Both { left: 3, right: 4 },
+            ]]
+        );
+    }
+
+    #[test]
+    fn gaps_inside_the_text() {
+        let (score, alignments) = align("abcdef", "xxabcZZZZdefxx", &score_func, &gap_penalty);
+        assert_eq!(score, 18.0 - gap_penalty(4));
+        assert_eq!(alignments.len(), 1);
+        assert_eq!(alignments[0][0], AlignmentCell::Both { left: 0, right: 2 });
+        assert_eq!(alignments[0].len(), 10);
+    }
+
+    #[test]
+    fn every_match_is_found() {
+        let (score, alignments) = align("abc", "abc-abc", &score_func, &gap_penalty);
+        assert_eq!(score, 9.0);
+        assert_eq!(alignments.len(), 2);
+        assert_eq!(alignments[1][0], AlignmentCell::Both { left: 0, right: 4 });
+    }
+}
@@ -2,6 +2,8 @@ extern crate ordered_float;
 
 use alignment::align::*;
 
+// How the best score at a cell was reached: by aligning both elements, or by
+// ending a gap in the right (`Up`) or left (`Left`) sequence there.
 #[derive(Clone, Copy, Debug, PartialEq)]
 enum Direction {
     Diag,
@@ -9,10 +11,24 @@ enum Direction {
     Left,
 }
 
-type Directions = Vec<Direction>;
+// How a gap ending at a cell was reached.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum GapStep {
+    Open,
+    Extend,
+}
+
+// Gaps are affine, so each cell records how its best score was reached as
+// well as how the best gaps ending there were reached.
+#[derive(Clone, Debug, Default)]
+struct TracebackCell {
+    best: Vec<Direction>,
+    up: Vec<GapStep>,
+    left: Vec<GapStep>,
+}
 
 type ScoreMatrix = ndarray::Array2<f32>;
-type TracebackMatrix = ndarray::Array2<Directions>;
+type TracebackMatrix = ndarray::Array2<TracebackCell>;
 type Index = (ndarray::Ix, ndarray::Ix);
 type Traceback = Vec<Index>;
 
@@ -21,80 +37,153 @@ type Traceback = Vec<Index>;
 // Note that tracebacks are in reverse. The first element in the traceback is
 // the "biggest" index in the traceback, and they work their way backward
 // through the strings being aligned.
-fn tracebacks(traceback_matrix: &TracebackMatrix, idx: Index) -> Vec<Traceback> {
-    let directions = traceback_matrix.get(idx).expect("index is invalid");
-    if directions.is_empty() {
-        vec![vec![]]
-    } else {
-        let mut tbs: Vec<Traceback> = Vec::new();
-
-        let (row, col) = idx;
-
-        for dir in directions {
-            let tail_idx = match dir {
-                Direction::Up => (row - 1, col),
-                Direction::Diag => (row - 1, col - 1),
-                Direction::Left => (row, col - 1),
-            };
-
-            let mut tails = tracebacks(traceback_matrix, tail_idx);
-
-            for mut tail in tails {
-                let mut tb = vec![idx];
-                tb.extend(tail);
-                tbs.push(tb);
+//
+// Repetitive text can have huge numbers of equally good tracebacks, so they
+// are produced lazily.
+fn tracebacks<'a>(traceback_matrix: &'a TracebackMatrix, idx: Index) -> Tracebacks<'a> {
+    Tracebacks {
+        traceback_matrix,
+        pending: vec![(Traceback::new(), idx, None)],
+    }
+}
+
+// A depth-first walk over the tracebacks from a cell.
+struct Tracebacks<'a> {
+    traceback_matrix: &'a TracebackMatrix,
+
+    // Partial tracebacks still to be finished, each with the cell they
+    // continue from and, if they continue from a gap ending there, the
+    // direction of that gap.
+    pending: Vec<(Traceback, Index, Option<Direction>)>,
+}
+
+impl<'a> Iterator for Tracebacks<'a> {
+    type Item = Traceback;
+
+    fn next(&mut self) -> Option<Traceback> {
+        while let Some((traceback, idx, gap)) = self.pending.pop() {
+            let cell = self.traceback_matrix.get(idx).expect("index is invalid");
+            let (row, col) = idx;
+
+            // The steps back from here: the cell and gap they lead to, and
+            // whether `idx` itself is part of the traceback.
+            let mut steps: Vec<(Index, Option<Direction>, bool)> = vec![];
+
+            match gap {
+                None => {
+                    if cell.best.is_empty() {
+                        return Some(traceback);
+                    }
+
+                    for dir in &cell.best {
+                        match dir {
+                            Direction::Diag => steps.push(((row - 1, col - 1), None, true)),
+                            gap => steps.push((idx, Some(*gap), false)),
+                        }
+                    }
+                }
+                Some(Direction::Up) => {
+                    for step in &cell.up {
+                        let tail_gap = match step {
+                            GapStep::Open => None,
+                            GapStep::Extend => Some(Direction::Up),
+                        };
+                        steps.push(((row - 1, col), tail_gap, true));
+                    }
+                }
+                Some(Direction::Left) => {
+                    for step in &cell.left {
+                        let tail_gap = match step {
+                            GapStep::Open => None,
+                            GapStep::Extend => Some(Direction::Left),
+                        };
+                        steps.push(((row, col - 1), tail_gap, true));
+                    }
+                }
+                Some(Direction::Diag) => unreachable!("diagonal steps aren't gaps"),
+            }
+
+            // Push in reverse so that steps are explored in order.
+            for (tail_idx, tail_gap, includes_idx) in steps.into_iter().rev() {
+                let mut tb = traceback.clone();
+                if includes_idx {
+                    tb.push(idx);
+                }
+                self.pending.push((tb, tail_idx, tail_gap));
             }
         }
 
-        tbs
+        None
     }
 }
 
+// The maximum of `(value, label)` pairs, along with every label reaching it.
+fn best_of<T: Copy>(candidates: &[(T, f32)]) -> (f32, Vec<T>) {
+    let max_score = candidates
+        .iter()
+        .max_by_key(|n| ordered_float::OrderedFloat(n.1))
+        .unwrap()
+        .1;
+
+    let labels = candidates
+        .iter()
+        .filter(|n| n.1 == max_score)
+        .map(|n| n.0)
+        .collect();
+
+    (max_score, labels)
+}
+
 fn build_score_matrix(
     a: &str,
     b: &str,
     score_func: &ScoringFunction,
     gap_penalty: &GapPenaltyFunction,
 ) -> (ScoreMatrix, TracebackMatrix) {
-    let mut score_matrix = ScoreMatrix::zeros((a.len() + 1, b.len() + 1));
+    let (gap_open, gap_extend) = gap_costs(gap_penalty);
+    let shape = (a.chars().count() + 1, b.chars().count() + 1);
+
+    let mut score_matrix = ScoreMatrix::zeros(shape);
+
+    // The best scores of alignments ending in a gap in each direction.
+    let mut up_matrix = ScoreMatrix::from_elem(shape, f32::NEG_INFINITY);
+    let mut left_matrix = ScoreMatrix::from_elem(shape, f32::NEG_INFINITY);
 
-    let mut traceback_matrix =
-        TracebackMatrix::from_elem((a.len() + 1, b.len() + 1), Directions::new());
+    let mut traceback_matrix = TracebackMatrix::from_elem(shape, TracebackCell::default());
 
     for (row, a_char) in a.chars().enumerate() {
         for (col, b_char) in b.chars().enumerate() {
             let row = row + 1;
             let col = col + 1;
 
-            let scores = [
+            let (up_score, up_steps) = best_of(&[
+                (GapStep::Open, score_matrix[(row - 1, col)] - gap_open),
+                (GapStep::Extend, up_matrix[(row - 1, col)] - gap_extend),
+            ]);
+            up_matrix[(row, col)] = up_score;
+
+            let (left_score, left_steps) = best_of(&[
+                (GapStep::Open, score_matrix[(row, col - 1)] - gap_open),
+                (GapStep::Extend, left_matrix[(row, col - 1)] - gap_extend),
+            ]);
+            left_matrix[(row, col)] = left_score;
+
+            let (max_score, directions) = best_of(&[
                 (
                     Direction::Diag,
                     score_matrix[(row - 1, col - 1)] + score_func(a_char, b_char),
                 ),
-                (
-                    Direction::Up, 
-                    score_matrix[(row - 1, col)] - gap_penalty(1)),
-                (
-                    Direction::Left,
-                    score_matrix[(row, col - 1)] - gap_penalty(1),
-                ),
-            ];
+                (Direction::Up, up_score),
+                (Direction::Left, left_score),
+            ]);
 
-            let max_score = scores
-                .iter()
-                .max_by_key(|n| ordered_float::OrderedFloat(n.1))
-                .unwrap()
-                .1;
-
-            let directions: Vec<Direction> = scores
-                .iter()
-                .filter(|n| n.1 == max_score)
-                .map(|n| n.0)
-                .collect();
+            let cell = &mut traceback_matrix[(row, col)];
+            cell.up = up_steps;
+            cell.left = left_steps;
 
             if max_score > 0.0 {
                 score_matrix[(row, col)] = max_score;
-                traceback_matrix[(row, col)].extend(directions);
+                cell.best = directions;
             }
         }
     }
@@ -163,6 +252,17 @@ pub fn align(
     b: &str,
     score_func: &ScoringFunction,
     gap_penalty: &GapPenaltyFunction,
+) -> (f32, Vec<Alignment>) {
+    align_with_limit(a, b, score_func, gap_penalty, MAX_ALIGNMENTS)
+}
+
+// Like `align`, but returning at most `limit` alignments.
+pub fn align_with_limit(
+    a: &str,
+    b: &str,
+    score_func: &ScoringFunction,
+    gap_penalty: &GapPenaltyFunction,
+    limit: usize,
 ) -> (f32, Vec<Alignment>) {
     let (score_matrix, tb_matrix) = build_score_matrix(a, b, score_func, gap_penalty);
     let max_score = score_matrix
@@ -176,15 +276,16 @@ pub fn align(
         .map(|(index, _)| index)
         .collect();
 
-    let mut alignments = vec![];
-    for index in max_indices {
-        for traceback in tracebacks(&tb_matrix, index) {
-            match traceback_to_alignment(&traceback) {
-                Ok(alignment) => alignments.push(alignment),
-                Err(msg) => panic!(msg),
-            }
-        }
-    }
+    let alignments = max_indices
+        .into_iter()
+        .flat_map(|index| tracebacks(&tb_matrix, index))
+        .take(limit)
+        .map(|traceback| match traceback_to_alignment(&traceback) {
+            Ok(alignment) => alignment,
+            Err(msg) => panic!("{}", msg),
+        })
+        .collect();
+
     (*max_score, alignments)
 }
 
@@ -195,6 +296,11 @@ mod tests {
     use super::*;
     use scoring::*;
 
+    // The canned results are for gaps whose penalty doesn't depend on length.
+    fn linear_gap_penalty(gap: u32) -> f32 {
+        2.0 * gap as f32
+    }
+
     const INPUT1: &str = "GGTTGACTA";
     const INPUT2: &str = "TGTTACGG";
 
@@ -209,7 +315,7 @@ mod tests {
             .unwrap()
             .0;
 
-        let tbs = tracebacks(&traceback_matrix, max_idx);
+        let tbs: Vec<Traceback> = tracebacks(&traceback_matrix, max_idx).collect();
         assert_eq!(tbs.len(), 1);
 
         let expected = [(7, 6), (6, 5), (5, 4), (4, 4), (3, 3), (2, 2)];
@@ -233,11 +339,40 @@ mod tests {
         )
         .unwrap();
 
-        let (score_matrix, _) = build_score_matrix(INPUT1, INPUT2, &score_func, &gap_penalty);
+        let (score_matrix, _) =
+            build_score_matrix(INPUT1, INPUT2, &score_func, &linear_gap_penalty);
 
         assert_eq!(expected, score_matrix);
     }
 
+    #[test]
+    fn alignments_are_limited() {
+        let a = " ".repeat(20);
+        let b = " ".repeat(200);
+
+        let (max_score, alignments) = align_with_limit(&a, &b, &score_func, &gap_penalty, 5);
+        assert_eq!(max_score, 60.0);
+        assert_eq!(alignments.len(), 5);
+
+        let (_, alignments) = align(&a, &b, &score_func, &gap_penalty);
+        assert_eq!(alignments.len(), MAX_ALIGNMENTS);
+    }
+
+    #[test]
+    fn long_gaps_are_cheap() {
+        let a = "abcdefghij";
+        let b = format!("abcde{}fghij", "X".repeat(30));
+
+        let (max_score, alignments) = align(a, &b, &score_func, &gap_penalty);
+        assert_eq!(max_score, 30.0 - gap_penalty(30));
+        assert_eq!(alignments.len(), 1);
+        assert_eq!(alignments[0].len(), 40);
+
+        // With linear gaps only half of the text can be aligned.
+        let (max_score, _) = align(a, &b, &score_func, &linear_gap_penalty);
+        assert_eq!(max_score, 15.0);
+    }
+
     #[test]
     fn canned_alignment() {
         let (max_score, alignments) = align(INPUT1, INPUT2, &score_func, &gap_penalty);
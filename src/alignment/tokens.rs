@@ -0,0 +1,289 @@
+// Alignment of tokens rather than characters.
+//
+// Both sequences are split into words, whitespace runs and punctuation, and
+// the token sequences are aligned locally (see `linear_space`). A renamed
+// identifier is then a single cheap substitution rather than a run of
+// character mismatches that can pull the alignment elsewhere. The result is
+// mapped back to character indices, so callers see an ordinary alignment.
+//
+// Each distinct token is stood in for by a single character, which lets the
+// character aligners do the work.
+
+use std::collections::HashMap;
+
+use alignment::align::*;
+use alignment::linear_space;
+
+// How much two different words (or whitespace runs) score, as a fraction of
+// the score of the shorter one matching itself.
+const SUBSTITUTION_WEIGHT: f32 = 0.5;
+
+// Where the characters standing in for tokens start. Everything from here up
+// is a valid `char`.
+const FIRST_TOKEN_CHAR: u32 = 0x10000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TokenKind {
+    Word,
+    Whitespace,
+    Punctuation,
+}
+
+fn kind_of(c: char) -> TokenKind {
+    if c.is_alphanumeric() || c == '_' {
+        TokenKind::Word
+    } else if c.is_whitespace() {
+        TokenKind::Whitespace
+    } else {
+        TokenKind::Punctuation
+    }
+}
+
+// A token: its kind and the characters of the text it covers.
+#[derive(Debug, PartialEq)]
+struct Token {
+    kind: TokenKind,
+    start: usize,
+    len: usize,
+}
+
+// Split `text` into tokens. Words and whitespace runs are single tokens, and
+// each punctuation character is a token of its own.
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens: Vec<Token> = vec![];
+    for (index, c) in text.chars().enumerate() {
+        let kind = kind_of(c);
+        match tokens.last_mut() {
+            Some(ref mut token) if token.kind == kind && kind != TokenKind::Punctuation => {
+                token.len += 1
+            }
+            _ => tokens.push(Token {
+                kind,
+                start: index,
+                len: 1,
+            }),
+        }
+    }
+    tokens
+}
+
+// The distinct tokens of some texts, with the character standing in for
+// each.
+struct Vocabulary {
+    ids: HashMap<String, usize>,
+
+    // The kind of each distinct token, and its score when matched with itself.
+    entries: Vec<(TokenKind, f32)>,
+}
+
+impl Vocabulary {
+    fn new() -> Vocabulary {
+        Vocabulary {
+            ids: HashMap::new(),
+            entries: vec![],
+        }
+    }
+
+    // The string of stand-in characters for `tokens` of `text`, or `None` if
+    // there are too many distinct tokens to represent.
+    fn encode(
+        &mut self,
+        text: &[char],
+        tokens: &[Token],
+        score_func: &ScoringFunction,
+    ) -> Option<String> {
+        tokens
+            .iter()
+            .map(|token| {
+                let chars = &text[token.start..token.start + token.len];
+                let next_id = self.entries.len();
+                let id = *self.ids.entry(chars.iter().collect()).or_insert(next_id);
+                if id == next_id {
+                    let score = chars.iter().map(|c| score_func(*c, *c)).sum();
+                    self.entries.push((token.kind, score));
+                }
+                std::char::from_u32(FIRST_TOKEN_CHAR + id as u32)
+            })
+            .collect()
+    }
+
+    // A scoring function for the stand-in characters.
+    //
+    // Identical tokens score as their characters would. Different tokens of
+    // the same kind partly match, and any other pair mismatches as badly as
+    // the shorter token would match.
+    fn scoring_function(self) -> impl Fn(char, char) -> f32 {
+        let entries = self.entries;
+        move |a, b| {
+            let (a_kind, a_score) = entries[a as usize - FIRST_TOKEN_CHAR as usize];
+            let (b_kind, b_score) = entries[b as usize - FIRST_TOKEN_CHAR as usize];
+            if a == b {
+                a_score
+            } else if a_kind == b_kind && a_kind != TokenKind::Punctuation {
+                SUBSTITUTION_WEIGHT * a_score.min(b_score)
+            } else {
+                -a_score.min(b_score)
+            }
+        }
+    }
+}
+
+// Expand an alignment of tokens into an alignment of their characters.
+//
+// The characters of aligned tokens are paired from the start, except that
+// their last characters are always paired so that a token is covered by the
+// alignment from its first character to its last.
+fn expand(alignment: &[AlignmentCell], a: &[Token], b: &[Token]) -> Alignment {
+    let mut expanded = Alignment::new();
+    for cell in alignment {
+        match *cell {
+            AlignmentCell::Both { left, right } => {
+                let (a, b) = (&a[left], &b[right]);
+                let paired = a.len.min(b.len) - 1;
+                for index in 0..paired {
+                    expanded.push(AlignmentCell::Both {
+                        left: a.start + index,
+                        right: b.start + index,
+                    });
+                }
+                for index in paired..a.len - 1 {
+                    expanded.push(AlignmentCell::RightGap {
+                        left: a.start + index,
+                    });
+                }
+                for index in paired..b.len - 1 {
+                    expanded.push(AlignmentCell::LeftGap {
+                        right: b.start + index,
+                    });
+                }
+                expanded.push(AlignmentCell::Both {
+                    left: a.start + a.len - 1,
+                    right: b.start + b.len - 1,
+                });
+            }
+            AlignmentCell::RightGap { left } => {
+                let a = &a[left];
+                for index in a.start..a.start + a.len {
+                    expanded.push(AlignmentCell::RightGap { left: index });
+                }
+            }
+            AlignmentCell::LeftGap { right } => {
+                let b = &b[right];
+                for index in b.start..b.start + b.len {
+                    expanded.push(AlignmentCell::LeftGap { right: index });
+                }
+            }
+        }
+    }
+    expanded
+}
+
+pub fn align(
+    a: &str,
+    b: &str,
+    score_func: &ScoringFunction,
+    gap_penalty: &GapPenaltyFunction,
+) -> (f32, Vec<Alignment>) {
+    align_with_limit(a, b, score_func, gap_penalty, MAX_ALIGNMENTS)
+}
+
+// Like `align`, but returning at most `limit` alignments.
+//
+// Gaps are penalized per token, so `gap_penalty` is given numbers of tokens.
+pub fn align_with_limit(
+    a: &str,
+    b: &str,
+    score_func: &ScoringFunction,
+    gap_penalty: &GapPenaltyFunction,
+    limit: usize,
+) -> (f32, Vec<Alignment>) {
+    let a_chars: Vec<char> = a.chars().collect();
+    let b_chars: Vec<char> = b.chars().collect();
+    let a_tokens = tokenize(a);
+    let b_tokens = tokenize(b);
+
+    let mut vocabulary = Vocabulary::new();
+    let encoded = (
+        vocabulary.encode(&a_chars, &a_tokens, score_func),
+        vocabulary.encode(&b_chars, &b_tokens, score_func),
+    );
+    let (a_encoded, b_encoded) = match encoded {
+        (Some(a_encoded), Some(b_encoded)) => (a_encoded, b_encoded),
+        _ => return linear_space::align_with_limit(a, b, score_func, gap_penalty, limit),
+    };
+
+    let token_score_func = vocabulary.scoring_function();
+    let (score, alignments) = linear_space::align_with_limit(
+        &a_encoded,
+        &b_encoded,
+        &token_score_func,
+        gap_penalty,
+        limit,
+    );
+
+    let alignments = alignments
+        .iter()
+        .map(|alignment| expand(alignment, &a_tokens, &b_tokens))
+        .collect();
+
+    (score, alignments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scoring::*;
+
+    #[test]
+    fn tokens_are_words_whitespace_and_punctuation() {
+        let tokens = tokenize("x_1 =  f(y);");
+        let spans: Vec<(TokenKind, usize, usize)> = tokens
+            .iter()
+            .map(|token| (token.kind, token.start, token.len))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                (TokenKind::Word, 0, 3),
+                (TokenKind::Whitespace, 3, 1),
+                (TokenKind::Punctuation, 4, 1),
+                (TokenKind::Whitespace, 5, 2),
+                (TokenKind::Word, 7, 1),
+                (TokenKind::Punctuation, 8, 1),
+                (TokenKind::Word, 9, 1),
+                (TokenKind::Punctuation, 10, 1),
+                (TokenKind::Punctuation, 11, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn unchanged_text_scores_as_characters() {
+        let text = "def func(x):";
+        let (score, alignments) = align(text, text, &score_func, &gap_penalty);
+        assert_eq!(score, 3.0 * text.len() as f32);
+
+        let expected: Alignment = (0..text.len())
+            .map(|index| AlignmentCell::Both {
+                left: index,
+                right: index,
+            })
+            .collect();
+        assert_eq!(alignments, vec![expected]);
+    }
+
+    #[test]
+    fn renamed_words_stay_aligned() {
+        let (_, alignments) = align("f(x, y)", "f(first, y)", &score_func, &gap_penalty);
+        assert_eq!(alignments.len(), 1);
+
+        let alignment = &alignments[0];
+        assert_eq!(alignment[0], AlignmentCell::Both { left: 0, right: 0 });
+        assert!(alignment.contains(&AlignmentCell::Both { left: 2, right: 6 }));
+        assert!(alignment.contains(&AlignmentCell::LeftGap { right: 2 }));
+        assert_eq!(
+            alignment.last(),
+            Some(&AlignmentCell::Both { left: 6, right: 10 })
+        );
+    }
+}
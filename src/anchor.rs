@@ -2,7 +2,7 @@ use std::cmp::max;
 use std::io::{Error, ErrorKind, Result};
 use std::path::{Path, PathBuf};
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Context {
     before: String,
     offset: usize,
@@ -12,6 +12,12 @@ pub struct Context {
 }
 
 impl Context {
+    /// The context of the topic starting `offset` characters into `text` and
+    /// `width` characters wide, with up to `context_width` characters on
+    /// either side.
+    ///
+    /// Offsets and widths are always counted in characters (Unicode scalar
+    /// values). See `position::OffsetUnit` for converting other units.
     pub fn new(text: &str, offset: usize, width: usize, context_width: usize) -> Result<Context> {
         let topic: String = text
             .chars()
@@ -19,7 +25,7 @@ impl Context {
             .take(width)
             .collect();
 
-        if topic.len() < width {
+        if topic.chars().count() < width {
             return Err(Error::new(ErrorKind::InvalidInput, "Unable to read topic"));
         }
 
@@ -38,11 +44,10 @@ impl Context {
 
         // read after
         let after_offset = offset + width;
-        let after_width = after_offset + context_width;
         let after: String = text
             .chars()
             .skip(after_offset)
-            .take(after_width)
+            .take(context_width)
             .collect();
 
         let context = Context {
@@ -81,12 +86,14 @@ impl Context {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Anchor {
     file_path: PathBuf,
     encoding: String,
     context: Context,
     metadata: serde_yaml::Value,
+    #[serde(default)]
+    scoring: Option<String>,
 }
 
 impl Anchor {
@@ -108,6 +115,7 @@ impl Anchor {
             encoding: encoding,
             context: context,
             metadata: metadata,
+            scoring: None,
         };
 
         Ok(anchor)
@@ -128,6 +136,26 @@ impl Anchor {
     pub fn metadata(&self) -> &serde_yaml::Value {
         return &self.metadata;
     }
+
+    /// The name of the scoring profile used to update the anchor, if not the
+    /// default. See `scoring::profile`.
+    pub fn scoring(&self) -> Option<&str> {
+        self.scoring.as_deref()
+    }
+
+    /// The anchor, updated using the scoring profile `scoring`.
+    pub fn with_scoring(mut self, scoring: Option<String>) -> Anchor {
+        self.scoring = scoring;
+        self
+    }
+
+    /// The anchor, moved to the file `file_path`, e.g. after the file it was
+    /// in has been renamed. `file_path` must be absolute.
+    pub fn with_file_path(mut self, file_path: &Path) -> Anchor {
+        assert!(file_path.is_absolute(), "Anchor file paths must be absolute");
+        self.file_path = file_path.to_path_buf();
+        self
+    }
 }
 
 #[cfg(test)]
@@ -144,5 +172,24 @@ mod tests {
         fn construct_context_with_topic_at_front_of_file() {
             Context::new("text", 0, 4, 3).unwrap();
         }
+
+        #[test]
+        fn context_is_limited_to_context_width() {
+            let context = Context::new("0123456789abcdef", 6, 2, 3).unwrap();
+            assert_eq!(context.before(), "345");
+            assert_eq!(context.topic(), "67");
+            assert_eq!(context.after(), "89a");
+        }
+
+        #[test]
+        fn widths_are_counted_in_characters() {
+            let context = Context::new("aé𝄞bc", 1, 2, 2).unwrap();
+            assert_eq!(context.before(), "a");
+            assert_eq!(context.topic(), "é𝄞");
+            assert_eq!(context.after(), "bc");
+
+            // "é𝄞" is six bytes, but only two characters.
+            assert!(Context::new("aé𝄞", 1, 3, 2).is_err());
+        }
     }
 }
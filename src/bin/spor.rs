@@ -6,22 +6,31 @@ extern crate log;
 #[macro_use]
 extern crate serde_derive;
 
+extern crate atty;
 extern crate docopt;
 extern crate exit_code;
 extern crate serde_yaml;
 extern crate simple_logger;
 extern crate spor;
 
+use std::io::Write;
 use std::iter::FromIterator;
 use std::path::PathBuf;
 
 use docopt::Docopt;
-use spor::alignment::smith_waterman::align;
+use spor::alignment::registry;
 use spor::anchor::{Anchor, Context};
-use spor::diff::get_anchor_diff;
+use spor::diff::{diff_lines, get_anchor_diff};
+use spor::editor;
 use spor::file_io::read_file;
-use spor::repository::{AnchorId, Repository};
-use spor::updating::update;
+use spor::fsck::Problem;
+use spor::parallel;
+use spor::position::{Location, Position};
+use spor::repository::{AnchorFilter, AnchorId, Pathspec, Repository, StoreKind};
+use spor::scoring;
+use spor::status::{self, Status};
+use spor::relocation::{update_or_relocate, Candidates};
+use spor::updating::{UpdateError, UpdateOptions};
 
 const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
@@ -29,18 +38,44 @@ const USAGE: &'static str = "
 spor
 
 Usage:
-  spor init
-  spor add <source-file> <offset> <width> <context-width>
+  spor init [--store=<kind>]
+  spor add <source-file> <offset> <width> <context-width> [--scoring=<profile>]
+  spor add <location> [<width>] [--context-width=<n>] [--scoring=<profile>]
   spor list <source-file>
+  spor at <source-file> <position>
   spor details <id>
+  spor edit <id>
+  spor set-meta <id> <yaml-path> <value>
+  spor unset-meta <id> <yaml-path>
   spor diff <anchor-id>
-  spor status
-  spor update
+  spor remove [--dry-run] <id-prefix>...
+  spor status [--jobs=<n>] [--all | --state=<state>...] [--id=<prefix>...] [<pathspec>...]
+  spor update [--algorithm=<name>] [--scoring=<profile>] [--threshold=<fraction>] [--jobs=<n>] [--dry-run | --confirm] [--id=<prefix>...] [<pathspec>...]
+  spor reindex
+  spor migrate
+  spor fsck [--quarantine]
   spor (-h | --help | --version)
 
 Options:
   -h, --help     Show this screen.
   --version     Show version.
+  --dry-run     Print what would be done without changing anything.
+  --confirm     Ask before moving each anchor.
+  --context-width=<n>  Characters of context kept around the topic [default: 10].
+  --quarantine  Move unreadable anchor data out of the way.
+  --store=<kind>  How to store anchors: directory or single-file [default: directory].
+  --algorithm=<name>  How to align anchors with changed files: local, smith-waterman,
+                      semi-global, needleman-wunsch, tokens or exact.
+  --scoring=<profile>  How to compare text: default, whitespace-insensitive,
+                       case-insensitive or comments. Given to update, this
+                       overrides each anchor's own profile.
+  --threshold=<fraction>  How good a match, as a fraction of a perfect one, an anchor's
+                          new location must be [default: 0.5].
+  --all  Show intact anchors as well as changed ones.
+  --state=<state>  Show only anchors in this state: intact, moved, topic-modified,
+                   context-modified, lost or file-missing.
+  --id=<prefix>  Only process anchors whose ID starts with this.
+  --jobs=<n>  How many anchors to process at once, or 0 for one per CPU [default: 0].
 ";
 
 #[derive(Debug, Deserialize)]
@@ -48,26 +83,57 @@ struct Args {
     cmd_init: bool,
     cmd_add: bool,
     cmd_list: bool,
+    cmd_at: bool,
     cmd_status: bool,
     cmd_update: bool,
     cmd_details: bool,
+    cmd_edit: bool,
+    cmd_set_meta: bool,
+    cmd_unset_meta: bool,
     cmd_diff: bool,
+    cmd_remove: bool,
+    cmd_reindex: bool,
+    cmd_migrate: bool,
+    cmd_fsck: bool,
     arg_source_file: String,
-    arg_offset: usize,
-    arg_width: usize,
-    arg_context_width: usize,
+    arg_offset: Option<usize>,
+    arg_width: Option<usize>,
+    arg_context_width: Option<usize>,
+    arg_location: String,
+    arg_position: String,
     arg_id: String,
     arg_anchor_id: String,
+    arg_id_prefix: Vec<String>,
+    arg_pathspec: Vec<String>,
+    arg_yaml_path: String,
+    arg_value: String,
+    flag_context_width: usize,
+    flag_dry_run: bool,
+    flag_confirm: bool,
+    flag_quarantine: bool,
+    flag_store: String,
+    flag_algorithm: Option<String>,
+    flag_scoring: Option<String>,
+    flag_threshold: f32,
+    flag_jobs: usize,
+    flag_all: bool,
+    flag_state: Vec<String>,
+    flag_id: Vec<String>,
     flag_help: bool,
     flag_version: bool,
 }
 
 type CommandResult = std::result::Result<(), i32>;
 
-fn init_handler() -> CommandResult {
+fn init_handler(args: &Args) -> CommandResult {
     let path = std::env::current_dir().map_err(|_| exit_code::OS_FILE_ERROR)?;
 
-    spor::repository::initialize(&path, None).map_err(|_| exit_code::DATA_ERROR)?;
+    let store: StoreKind = args.flag_store.parse().map_err(|e| {
+        println!("{}", e);
+        exit_code::USAGE_ERROR
+    })?;
+
+    spor::repository::initialize_with_store(&path, None, store).map_err(|_| exit_code::DATA_ERROR)?;
 
     Ok(())
 }
@@ -87,13 +153,33 @@ fn add_handler(args: &Args) -> CommandResult {
 
     let repo = open_repo(&path)?;
 
-    let metadata = serde_yaml::from_reader(std::io::stdin()).map_err(|e| {
-        println!("{:?}", e);
-        exit_code::DATA_ERROR
-    })?;
+    if let Some(ref name) = args.flag_scoring {
+        scoring::profile(name).map_err(|e| {
+            println!("{}", e);
+            exit_code::USAGE_ERROR
+        })?;
+    }
 
     let encoding = "utf-8".to_string();
-    let full_path = std::path::Path::new(&args.arg_source_file)
+
+    // The topic is given either as an explicit offset or as a location
+    // suffix on the file name, e.g. "file.py:3" or "file.py:3:5".
+    let (source_file, location) = if args.arg_location.is_empty() {
+        (args.arg_source_file.as_str(), None)
+    } else {
+        match Location::split(&args.arg_location) {
+            (file, Some(location)) => (file, Some(location)),
+            (_, None) => {
+                println!(
+                    "Expected file:line, file:line:col or file:start-end, got {}",
+                    args.arg_location
+                );
+                return Err(exit_code::USAGE_ERROR);
+            }
+        }
+    };
+
+    let full_path = std::path::Path::new(source_file)
         .canonicalize()
         .map_err(|e| {
             println!("{:?}", e);
@@ -104,18 +190,59 @@ fn add_handler(args: &Args) -> CommandResult {
         exit_code::DATA_ERROR
     })?;
 
-    let anchor = Context::new(
-        &full_text,
-        args.arg_offset,
-        args.arg_width,
-        args.arg_context_width,
-    )
-    .and_then(|c| Anchor::new(&full_path, c, metadata, encoding))
-    .map_err(|e| {
+    let (offset, width) = match location {
+        None => (args.arg_offset.unwrap_or(0), args.arg_width.unwrap_or(0)),
+        Some(location) => {
+            let needs_width = matches!(location, Location::LineCol(..));
+
+            if needs_width && args.arg_width.is_none() {
+                println!("A width is required with file:line:col");
+                return Err(exit_code::USAGE_ERROR);
+            } else if !needs_width && args.arg_width.is_some() {
+                println!("A width can't be given with whole lines");
+                return Err(exit_code::USAGE_ERROR);
+            }
+
+            location.to_span(&full_text, args.arg_width).ok_or_else(|| {
+                println!("Location {} not found", args.arg_location);
+                exit_code::DATA_ERROR
+            })?
+        }
+    };
+
+    let context_width = args.arg_context_width.unwrap_or(args.flag_context_width);
+
+    let context = Context::new(&full_text, offset, width, context_width).map_err(|e| {
         println!("{:?}", e);
         exit_code::DATA_ERROR
     })?;
 
+    // Interactive users get an editor; otherwise metadata is piped in.
+    let metadata = if atty::is(atty::Stream::Stdin) {
+        editor::metadata_template(
+            std::path::Path::new(source_file),
+            Some(context.topic()),
+            &serde_yaml::Value::Null,
+        )
+        .and_then(|template| editor::edit_metadata(&template))
+        .map_err(|e| {
+            println!("{:?}", e);
+            exit_code::DATA_ERROR
+        })?
+    } else {
+        serde_yaml::from_reader(std::io::stdin()).map_err(|e| {
+            println!("{:?}", e);
+            exit_code::DATA_ERROR
+        })?
+    };
+
+    let anchor = Anchor::new(&full_path, context, metadata, encoding)
+        .map_err(|e| {
+            println!("{:?}", e);
+            exit_code::DATA_ERROR
+        })?
+        .with_scoring(args.flag_scoring.clone());
+
     repo.add(anchor).map_err(|e| {
         println!("{:?}", e);
         exit_code::OS_FILE_ERROR
@@ -127,32 +254,108 @@ fn add_handler(args: &Args) -> CommandResult {
 fn list_handler(args: &Args) -> CommandResult {
     let file = std::path::Path::new(&args.arg_source_file);
     let repo = open_repo(&file.to_path_buf())?;
-    for (id, anchor) in &repo {
-        println!(
-            "{} {:?}:{} => {:?}",
-            id,
-            anchor.file_path(),
-            anchor.context().offset(),
-            anchor.metadata()
-        );
+    let anchors = repo.anchors_in(file).map_err(|e| {
+        println!("{:?}", e);
+        exit_code::OS_FILE_ERROR
+    })?;
+
+    for (id, anchor) in anchors {
+        print_listing(&id, &anchor);
+    }
+
+    Ok(())
+}
+
+fn print_listing(id: &AnchorId, anchor: &Anchor) {
+    println!(
+        "{} {:?}:{} => {:?}",
+        id,
+        anchor.file_path(),
+        anchor.context().offset(),
+        anchor.metadata()
+    );
+}
+
+fn at_handler(args: &Args) -> CommandResult {
+    let file = std::path::Path::new(&args.arg_source_file);
+    let repo = open_repo(&file.to_path_buf())?;
+
+    let position: Position = args.arg_position.parse().map_err(|e| {
+        println!("{}", e);
+        exit_code::USAGE_ERROR
+    })?;
+
+    let offset = match position {
+        Position::Offset(offset) => offset,
+        _ => {
+            let full_text = read_file(file, "utf-8").map_err(|e| {
+                println!("{:?}", e);
+                exit_code::DATA_ERROR
+            })?;
+            position.to_offset(&full_text).ok_or_else(|| {
+                println!("Position {} not found in {:?}", args.arg_position, file);
+                exit_code::DATA_ERROR
+            })?
+        }
+    };
+
+    let anchors = repo.anchors_at(file, offset).map_err(|e| {
+        println!("{:?}", e);
+        exit_code::OS_FILE_ERROR
+    })?;
+
+    for (id, anchor) in anchors {
+        print_listing(&id, &anchor);
     }
 
     Ok(())
 }
 
-fn status_handler(_args: &Args) -> CommandResult {
+fn status_handler(args: &Args) -> CommandResult {
     let file = std::path::Path::new(".");
     let repo = open_repo(&file.to_path_buf())?;
 
-    for (id, anchor) in &repo {
-        let (changed, _diffs) = get_anchor_diff(&anchor).map_err(|_e| exit_code::OS_FILE_ERROR)?;
+    // Intact anchors are only shown when asked for.
+    let shown: Vec<Status> = if args.flag_all {
+        Status::ALL.to_vec()
+    } else if args.flag_state.is_empty() {
+        Status::ALL
+            .iter()
+            .cloned()
+            .filter(|status| *status != Status::Intact)
+            .collect()
+    } else {
+        args.flag_state
+            .iter()
+            .map(|name| name.parse())
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|e| {
+                println!("{}", e);
+                exit_code::USAGE_ERROR
+            })?
+    };
+
+    let align = aligner(args, &repo)?;
+    let options = UpdateOptions::default();
+
+    let anchors = selected_anchors(args, &repo)?;
+    let statuses = parallel::map(&anchors, args.flag_jobs, |(_, anchor)| {
+        status::classify(anchor, &align, &options)
+    });
+
+    for ((id, anchor), status) in anchors.iter().zip(statuses) {
+        let status = status.map_err(|e| {
+            println!("{:?}", e);
+            exit_code::OS_FILE_ERROR
+        })?;
 
-        if changed {
+        if shown.contains(&status) {
             println!(
-                "{} {}:{} out-of-date",
+                "{} {}:{} {}",
                 id,
                 anchor.file_path().to_string_lossy(),
-                anchor.context().offset()
+                anchor.context().offset(),
+                status
             );
         }
     }
@@ -174,16 +377,89 @@ fn diff_handler(args: &Args) -> CommandResult {
     Ok(())
 }
 
-fn update_handler(_args: &Args) -> CommandResult {
+/// The alignment algorithm to use. The command line takes precedence over the
+/// repository's config.
+fn aligner(args: &Args, repo: &Repository) -> std::result::Result<registry::AlignFn, i32> {
+    let algorithm = args
+        .flag_algorithm
+        .as_deref()
+        .or_else(|| repo.algorithm())
+        .unwrap_or(registry::DEFAULT_ALGORITHM);
+    registry::by_name(algorithm).map_err(|e| {
+        println!("{}", e);
+        exit_code::USAGE_ERROR
+    })
+}
+
+fn update_handler(args: &Args) -> CommandResult {
     let file = std::path::Path::new(".");
 
     let repo = Repository::new(file, None).map_err(|_| exit_code::OS_FILE_ERROR)?;
 
-    for (id, anchor) in &repo {
-        let updated = update(&anchor, &align).map_err(|e| {
-            println!("{:?}", e);
-            exit_code::DATA_ERROR
-        })?;
+    let align = aligner(args, &repo)?;
+
+    let options = UpdateOptions {
+        scoring: match args.flag_scoring {
+            Some(ref name) => Some(scoring::profile(name).map_err(|e| {
+                println!("{}", e);
+                exit_code::USAGE_ERROR
+            })?),
+            None => None,
+        },
+        threshold: args.flag_threshold,
+        ..UpdateOptions::default()
+    };
+
+    // Anchors are aligned in parallel, but reported and saved in order.
+    // Anchors whose files are gone are looked for in the other files of the
+    // types that are anchored.
+    let anchors = selected_anchors(args, &repo)?;
+    let candidates = Candidates::new(&repo.root)
+        .with_extensions(anchors.iter().map(|(_, anchor)| anchor.file_path().extension()));
+    let results = parallel::map(&anchors, args.flag_jobs, |(_, anchor)| {
+        update_or_relocate(anchor, &candidates, &align, &options)
+    });
+
+    // Anchors that can't be updated are reported and left as they are.
+    let mut failed = false;
+    for ((id, anchor), result) in anchors.into_iter().zip(results) {
+        let updated = match result {
+            Ok((updated, confidence)) => {
+                // Anchors that haven't changed aren't shown or rewritten.
+                if is_unchanged(&anchor, &updated) {
+                    continue;
+                }
+
+                if args.flag_dry_run || args.flag_confirm {
+                    print_move(&id, &anchor, &updated, confidence);
+                    if args.flag_dry_run || !confirm("Move this anchor?") {
+                        continue;
+                    }
+                }
+                updated
+            }
+            Err(UpdateError::Orphaned) => {
+                println!(
+                    "{} {}:{} orphaned",
+                    id,
+                    anchor.file_path().to_string_lossy(),
+                    anchor.context().offset()
+                );
+                failed = true;
+                continue;
+            }
+            Err(e) => {
+                println!(
+                    "{} {}:{} not updated: {}",
+                    id,
+                    anchor.file_path().to_string_lossy(),
+                    anchor.context().offset(),
+                    e
+                );
+                failed = true;
+                continue;
+            }
+        };
 
         repo.update(id, &updated).map_err(|e| {
             println!("{:?}", e);
@@ -191,9 +467,247 @@ fn update_handler(_args: &Args) -> CommandResult {
         })?;
     }
 
+    if failed {
+        Err(exit_code::DATA_ERROR)
+    } else {
+        Ok(())
+    }
+}
+
+/// Whether updating `old` gave `new` without moving it or changing its text.
+fn is_unchanged(old: &Anchor, new: &Anchor) -> bool {
+    new.file_path() == old.file_path()
+        && new.context().offset() == old.context().offset()
+        && new.context().full_text() == old.context().full_text()
+}
+
+/// Print the old and new locations of an anchor, and how its topic changed.
+fn print_move(id: &AnchorId, old: &Anchor, new: &Anchor, confidence: f32) {
+    // The new file is only given if the anchor moves to another file.
+    let new_location = if new.file_path() == old.file_path() {
+        new.context().offset().to_string()
+    } else {
+        format!("{}:{}", new.file_path().to_string_lossy(), new.context().offset())
+    };
+
+    println!(
+        "{} {}:{} -> {} (confidence {:.2})",
+        id,
+        old.file_path().to_string_lossy(),
+        old.context().offset(),
+        new_location,
+        confidence
+    );
+
+    let (_changed, diff) = diff_lines(old.context().topic(), new.context().topic());
+    for line in diff {
+        println!("    {}", line);
+    }
+}
+
+/// Ask the user a yes-or-no question. Anything but yes is no.
+fn confirm(question: &str) -> bool {
+    print!("{} [y/N] ", question);
+    let _ = std::io::stdout().flush();
+
+    let mut answer = String::new();
+    match std::io::stdin().read_line(&mut answer) {
+        Ok(_) => {
+            let answer = answer.trim().to_lowercase();
+            answer == "y" || answer == "yes"
+        }
+        Err(_) => false,
+    }
+}
+
+fn edit_handler(args: &Args) -> CommandResult {
+    let file = std::path::Path::new(".");
+    let repo = open_repo(&file.to_path_buf())?;
+
+    let (id, anchor) = get_anchor(&repo, &args.arg_id)?;
+
+    let template = editor::metadata_template(
+        anchor.file_path(),
+        Some(anchor.context().topic()),
+        anchor.metadata(),
+    )
+    .map_err(|e| {
+        println!("{:?}", e);
+        exit_code::DATA_ERROR
+    })?;
+
+    let edited = editor::edit_metadata(&template).map_err(|e| {
+        println!("{:?}", e);
+        exit_code::DATA_ERROR
+    })?;
+
+    repo.update_metadata(&id, |metadata| {
+        *metadata = edited;
+        Ok(())
+    })
+    .map_err(|e| {
+        println!("{:?}", e);
+        exit_code::OS_FILE_ERROR
+    })?;
+
+    Ok(())
+}
+
+fn set_meta_handler(args: &Args) -> CommandResult {
+    let file = std::path::Path::new(".");
+    let repo = open_repo(&file.to_path_buf())?;
+
+    let (id, _anchor) = get_anchor(&repo, &args.arg_id)?;
+
+    // The value is YAML, so "42" and "[a, b]" become a number and a list.
+    let value: serde_yaml::Value = serde_yaml::from_str(&args.arg_value).map_err(|e| {
+        println!("{:?}", e);
+        exit_code::USAGE_ERROR
+    })?;
+
+    repo.update_metadata(&id, |metadata| {
+        spor::metadata::set_path(metadata, &args.arg_yaml_path, value)
+    })
+    .map_err(|e| {
+        println!("{:?}", e);
+        exit_code::DATA_ERROR
+    })?;
+
+    Ok(())
+}
+
+fn unset_meta_handler(args: &Args) -> CommandResult {
+    let file = std::path::Path::new(".");
+    let repo = open_repo(&file.to_path_buf())?;
+
+    let (id, _anchor) = get_anchor(&repo, &args.arg_id)?;
+
+    repo.update_metadata(&id, |metadata| {
+        spor::metadata::unset_path(metadata, &args.arg_yaml_path).map(|_| ())
+    })
+    .map_err(|e| {
+        println!("{:?}", e);
+        exit_code::DATA_ERROR
+    })?;
+
+    Ok(())
+}
+
+fn remove_handler(args: &Args) -> CommandResult {
+    let file = std::path::Path::new(".");
+    let repo = open_repo(&file.to_path_buf())?;
+
+    // Resolve every prefix before removing anything so that a bad prefix
+    // doesn't leave the repository half-modified.
+    let mut anchors: Vec<(AnchorId, Anchor)> = Vec::new();
+    for id_prefix in &args.arg_id_prefix {
+        let (id, anchor) = get_anchor(&repo, id_prefix)?;
+        if !anchors.iter().any(|(existing, _)| *existing == id) {
+            anchors.push((id, anchor));
+        }
+    }
+
+    for (id, anchor) in anchors {
+        if args.flag_dry_run {
+            println!(
+                "would remove {} {}:{}",
+                id,
+                anchor.file_path().to_string_lossy(),
+                anchor.context().offset()
+            );
+        } else {
+            repo.remove(&id).map_err(|e| {
+                println!("{:?}", e);
+                exit_code::OS_FILE_ERROR
+            })?;
+        }
+    }
+
     Ok(())
 }
 
+fn reindex_handler(_args: &Args) -> CommandResult {
+    let file = std::path::Path::new(".");
+    let repo = open_repo(&file.to_path_buf())?;
+
+    repo.reindex().map_err(|e| {
+        println!("{:?}", e);
+        exit_code::OS_FILE_ERROR
+    })
+}
+
+fn migrate_handler(_args: &Args) -> CommandResult {
+    let file = std::path::Path::new(".");
+    let mut repo = open_repo(&file.to_path_buf())?;
+
+    let migrated = repo.migrate().map_err(|e| {
+        println!("{:?}", e);
+        exit_code::DATA_ERROR
+    })?;
+
+    println!("Migrated {} anchors to format version {}", migrated, repo.format_version());
+
+    Ok(())
+}
+
+fn fsck_handler(args: &Args) -> CommandResult {
+    let file = std::path::Path::new(".");
+    let repo = open_repo(&file.to_path_buf())?;
+
+    let mut unresolved = 0;
+    for problem in spor::fsck::check(&repo) {
+        println!("{}", problem);
+
+        match problem {
+            Problem::Unreadable(ref err) if args.flag_quarantine => {
+                let destination = repo.quarantine(err).map_err(|e| {
+                    println!("{:?}", e);
+                    exit_code::OS_FILE_ERROR
+                })?;
+                println!("  quarantined to {:?}", destination);
+            }
+            _ => unresolved += 1,
+        }
+    }
+
+    if unresolved > 0 {
+        Err(exit_code::DATA_ERROR)
+    } else {
+        Ok(())
+    }
+}
+
+/// The anchors selected by the pathspecs and ID prefixes on the command line,
+/// or all anchors if there are none. Pathspecs are relative to the current
+/// directory.
+fn selected_anchors(args: &Args, repo: &Repository) -> std::result::Result<Vec<(AnchorId, Anchor)>, i32> {
+    let mut filter = AnchorFilter::new();
+
+    if !args.arg_pathspec.is_empty() {
+        let cwd = std::env::current_dir()
+            .and_then(|dir| dir.canonicalize())
+            .map_err(|e| {
+                println!("{:?}", e);
+                exit_code::OS_FILE_ERROR
+            })?;
+        for spec in &args.arg_pathspec {
+            filter = filter.with_pathspec(Pathspec::new(spec, &cwd).map_err(|e| {
+                println!("{}", e);
+                exit_code::USAGE_ERROR
+            })?);
+        }
+    }
+
+    for prefix in &args.flag_id {
+        filter = filter.with_id_prefix(prefix);
+    }
+
+    repo.anchors_matching(&filter).map_err(|e| {
+        println!("{:?}", e);
+        exit_code::OS_FILE_ERROR
+    })
+}
+
 /// Find an anchor based on a prefix of its ID.
 /// If there is not exactly one match for the ID prefix, then this returns an error.
 fn get_anchor(repo: &Repository, id_prefix: &str) -> std::result::Result<(AnchorId, Anchor), i32> {
@@ -264,9 +778,11 @@ fn main() {
         .unwrap_or_else(|e| e.exit());
 
     let result = if args.cmd_init {
-        init_handler()
+        init_handler(&args)
     } else if args.cmd_list {
         list_handler(&args)
+    } else if args.cmd_at {
+        at_handler(&args)
     } else if args.cmd_status {
         status_handler(&args)
     } else if args.cmd_add {
@@ -275,8 +791,22 @@ fn main() {
         update_handler(&args)
     } else if args.cmd_details {
         details_handler(&args)
+    } else if args.cmd_edit {
+        edit_handler(&args)
+    } else if args.cmd_set_meta {
+        set_meta_handler(&args)
+    } else if args.cmd_unset_meta {
+        unset_meta_handler(&args)
     } else if args.cmd_diff {
         diff_handler(&args)
+    } else if args.cmd_remove {
+        remove_handler(&args)
+    } else if args.cmd_reindex {
+        reindex_handler(&args)
+    } else if args.cmd_migrate {
+        migrate_handler(&args)
+    } else if args.cmd_fsck {
+        fsck_handler(&args)
     } else {
         Err(exit_code::FAILURE)
     };
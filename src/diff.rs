@@ -9,7 +9,7 @@ pub fn get_anchor_diff(anchor: &Anchor) -> Result<(bool, Vec<String>)> {
     let context = Context::new(
         &full_text,
         anchor.context().offset(),
-        anchor.context().topic().len(),
+        anchor.context().topic().chars().count(),
         anchor.context().width())?;
 
     let new_anchor = Anchor::new(
@@ -19,14 +19,22 @@ pub fn get_anchor_diff(anchor: &Anchor) -> Result<(bool, Vec<String>)> {
         anchor.encoding().clone(),
     )?;
 
+    Ok(diff_lines(
+        anchor.context().full_text().as_str(),
+        new_anchor.context().full_text().as_str(),
+    ))
+}
+
+/// Diff the lines of `old` and `new`.
+///
+/// Returns: Whether they differ, and each line prefixed with `-` if it's only
+/// in `old`, `+` if it's only in `new`, or a space if it's in both.
+pub fn diff_lines(old: &str, new: &str) -> (bool, Vec<String>) {
     let mut diff_strings: Vec<String> = Vec::new();
 
     let mut changed = false;
 
-    for diff in diff::lines(
-        anchor.context().full_text().as_str(),
-        new_anchor.context().full_text().as_str(),
-    ) {
+    for diff in diff::lines(old, new) {
         changed = match diff {
             diff::Result::Both(_, _) => changed,
             _ => true
@@ -40,5 +48,5 @@ pub fn get_anchor_diff(anchor: &Anchor) -> Result<(bool, Vec<String>)> {
         diff_strings.push(diff_text);
     }
 
-    Ok((changed, diff_strings))
+    (changed, diff_strings)
 }
@@ -0,0 +1,169 @@
+extern crate serde_yaml;
+extern crate uuid;
+
+use std::env;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::process::Command;
+
+const ERROR_PREFIX: &str = "# ERROR: ";
+
+/// The user's preferred editor: `$VISUAL`, then `$EDITOR`, then `vi`.
+pub fn editor_command() -> String {
+    env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+/// Build the initial contents of a metadata file for editing.
+///
+/// `metadata` is the metadata to start with. If `topic` is given, it is
+/// included as comments so the user can see what they're annotating.
+pub fn metadata_template(
+    file_path: &Path,
+    topic: Option<&str>,
+    metadata: &serde_yaml::Value,
+) -> Result<String> {
+    let mut template = String::new();
+    template.push_str("# Enter the anchor metadata as YAML. Lines starting with '#' are\n");
+    template.push_str("# ignored. Save an empty file to abort.\n");
+
+    if let Some(topic) = topic {
+        template.push_str(&format!("#\n# Anchored text in {}:\n", file_path.display()));
+        for line in topic.lines() {
+            template.push_str(&format!("#   {}\n", line));
+        }
+    }
+
+    if !metadata.is_null() {
+        let yaml = serde_yaml::to_string(metadata)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        template.push_str(yaml.trim_start_matches("---\n"));
+        template.push('\n');
+    }
+
+    Ok(template)
+}
+
+/// Let the user edit metadata in their editor, starting from `template`.
+///
+/// The editor is re-opened, with the parse error noted at the top of the
+/// file, until the contents are valid YAML.
+pub fn edit_metadata(template: &str) -> Result<serde_yaml::Value> {
+    edit_metadata_with(&editor_command(), template)
+}
+
+/// Like `edit_metadata`, but using the editor command `editor`.
+///
+/// `editor` may include arguments, e.g. "code --wait".
+pub fn edit_metadata_with(editor: &str, template: &str) -> Result<serde_yaml::Value> {
+    let path = env::temp_dir().join(format!("spor-metadata-{}.yml", uuid::Uuid::new_v4()));
+    let result = edit_loop(editor, &path, template);
+    let _ = fs::remove_file(&path);
+    result
+}
+
+fn edit_loop(editor: &str, path: &Path, template: &str) -> Result<serde_yaml::Value> {
+    let mut contents = template.to_string();
+
+    loop {
+        fs::write(path, &contents)?;
+        run_editor(editor, path)?;
+        contents = fs::read_to_string(path)?;
+
+        let is_empty = contents
+            .lines()
+            .all(|l| l.trim().is_empty() || l.trim_start().starts_with('#'));
+        if is_empty {
+            return Err(Error::new(ErrorKind::Interrupted, "Empty metadata; aborting"));
+        }
+
+        match serde_yaml::from_str(&contents) {
+            Ok(metadata) => return Ok(metadata),
+            Err(err) => {
+                let body: Vec<&str> = contents
+                    .lines()
+                    .filter(|l| !l.starts_with(ERROR_PREFIX))
+                    .collect();
+                let error_lines: Vec<String> = err
+                    .to_string()
+                    .lines()
+                    .map(|l| format!("{}{}", ERROR_PREFIX, l))
+                    .collect();
+                contents = format!("{}\n{}\n", error_lines.join("\n"), body.join("\n"));
+            }
+        }
+    }
+}
+
+fn run_editor(editor: &str, path: &Path) -> Result<()> {
+    let mut words = editor.split_whitespace();
+    let program = words
+        .next()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "No editor configured"))?;
+
+    let status = Command::new(program).args(words).arg(path).status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::other(format!("Editor {} failed: {}", editor, status)))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Create an "editor" script that replaces the file with successive
+    /// entries from `responses`, one per invocation.
+    fn fake_editor(dir: &Path, responses: &[&str]) -> String {
+        let mut script = String::from("#!/bin/sh\ncount=$(cat \"$0.count\" 2>/dev/null || echo 0)\n");
+        script.push_str("echo $((count + 1)) > \"$0.count\"\ncase $count in\n");
+        for (index, response) in responses.iter().enumerate() {
+            script.push_str(&format!("  {}) printf '%s' '{}' > \"$1\" ;;\n", index, response));
+        }
+        script.push_str("esac\n");
+
+        let path = dir.join("editor.sh");
+        fs::write(&path, script).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn template_includes_topic_and_metadata() {
+        let metadata = serde_yaml::from_str("foo: bar").unwrap();
+        let template =
+            metadata_template(Path::new("source.py"), Some("x = 1\ny = 2"), &metadata).unwrap();
+
+        assert!(template.contains("#   x = 1\n#   y = 2\n"));
+        assert!(template.ends_with("foo: bar\n"));
+    }
+
+    #[test]
+    fn editor_reopens_on_invalid_yaml() {
+        let dir = tempdir::TempDir::new("spor_editor_tests").unwrap();
+        let editor = fake_editor(dir.path(), &["foo: [", "foo: bar"]);
+
+        let metadata = edit_metadata_with(&editor, "").unwrap();
+        assert_eq!(metadata, serde_yaml::from_str::<serde_yaml::Value>("foo: bar").unwrap());
+
+        let count = fs::read_to_string(format!("{}.count", editor)).unwrap();
+        assert_eq!(count.trim(), "2");
+    }
+
+    #[test]
+    fn empty_metadata_aborts() {
+        let dir = tempdir::TempDir::new("spor_editor_tests").unwrap();
+        let editor = fake_editor(dir.path(), &["# nothing here"]);
+
+        let err = edit_metadata_with(&editor, "").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Interrupted);
+    }
+}
@@ -0,0 +1,108 @@
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+use repository::{AnchorId, AnchorLoadError, Repository};
+
+/// A problem found while checking a repository.
+#[derive(Debug)]
+pub enum Problem {
+    /// Anchor data that couldn't be loaded.
+    Unreadable(AnchorLoadError),
+
+    /// An anchor whose source file doesn't exist.
+    MissingSource { anchor_id: AnchorId, file_path: PathBuf },
+
+    /// An anchor whose source file is not inside the repository.
+    OutsideRepository { anchor_id: AnchorId, file_path: PathBuf },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::Unreadable(err) => write!(f, "unreadable {}", err),
+            Problem::MissingSource { anchor_id, file_path } => {
+                write!(f, "missing-source {} {:?}", anchor_id, file_path)
+            }
+            Problem::OutsideRepository { anchor_id, file_path } => {
+                write!(f, "outside-repository {} {:?}", anchor_id, file_path)
+            }
+        }
+    }
+}
+
+/// Check every anchor in `repo` for problems.
+pub fn check(repo: &Repository) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    for entry in repo.try_iter() {
+        match entry {
+            Err(err) => problems.push(Problem::Unreadable(err)),
+            Ok((anchor_id, anchor)) => {
+                let file_path = normalize(anchor.file_path());
+                if !file_path.starts_with(&repo.root) {
+                    problems.push(Problem::OutsideRepository { anchor_id, file_path });
+                } else if !file_path.is_file() {
+                    problems.push(Problem::MissingSource { anchor_id, file_path });
+                }
+            }
+        }
+    }
+
+    problems
+}
+
+/// Lexically resolve "." and ".." components in `path`.
+///
+/// Anchor file paths are stored relative to the repository root, so a path
+/// like "../other/file.py" can point outside of it.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use repository::initialize;
+    use std::fs;
+
+    const ANCHOR: &str = "format_version: 4
+offset_unit: char
+encoding: utf-8
+metadata: {}
+context: {before: '', offset: 0, topic: text, after: '', width: 3}
+";
+
+    #[test]
+    fn check_reports_each_kind_of_problem() {
+        let dir = tempdir::TempDir::new("spor_fsck_tests").unwrap();
+        initialize(dir.path(), None).unwrap();
+        let spor_dir = dir.path().join(".spor");
+
+        fs::write(dir.path().join("present.txt"), "text").unwrap();
+        fs::write(spor_dir.join("ok.yml"), format!("file_path: present.txt\n{}", ANCHOR)).unwrap();
+        fs::write(spor_dir.join("missing.yml"), format!("file_path: missing.txt\n{}", ANCHOR)).unwrap();
+        fs::write(spor_dir.join("outside.yml"), format!("file_path: ../outside.txt\n{}", ANCHOR)).unwrap();
+        fs::write(spor_dir.join("broken.yml"), "context: [").unwrap();
+
+        let repo = Repository::new(dir.path(), None).unwrap();
+        let mut problems: Vec<String> = check(&repo)
+            .iter()
+            .map(|p| p.to_string().split(' ').next().unwrap().to_string())
+            .collect();
+        problems.sort();
+
+        assert_eq!(problems, vec!["missing-source", "outside-repository", "unreadable"]);
+    }
+}
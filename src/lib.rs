@@ -9,7 +9,14 @@ extern crate serde_derive;
 pub mod alignment;
 pub mod anchor;
 pub mod diff;
+pub mod editor;
 pub mod file_io;
+pub mod fsck;
+pub mod metadata;
+pub mod parallel;
+pub mod position;
+pub mod relocation;
 pub mod repository;
 pub mod scoring;
+pub mod status;
 pub mod updating;
@@ -0,0 +1,176 @@
+extern crate serde_yaml;
+
+use std::io::{Error, ErrorKind, Result};
+
+use self::serde_yaml::Value;
+
+/// Set the value at `path` in `metadata`, creating mappings as needed.
+///
+/// `path` is a dot-separated list of keys, e.g. "review.status". Numeric keys
+/// index into sequences, and an index one past the end appends.
+pub fn set_path(metadata: &mut Value, path: &str, value: Value) -> Result<()> {
+    let keys = parse_path(path)?;
+    let (last, parents) = keys.split_last().unwrap();
+
+    let mut node = metadata;
+    for key in parents {
+        node = child_mut(node, key, true)?;
+    }
+
+    if node.is_null() {
+        *node = Value::Mapping(serde_yaml::Mapping::new());
+    }
+
+    match node {
+        Value::Sequence(seq) => {
+            let index = parse_index(last)?;
+            if index < seq.len() {
+                seq[index] = value;
+            } else if index == seq.len() {
+                seq.push(value);
+            } else {
+                return Err(not_found(path));
+            }
+        }
+        Value::Mapping(m) => {
+            m.insert(Value::String(last.to_string()), value);
+        }
+        _ => return Err(not_a_container(path)),
+    }
+
+    Ok(())
+}
+
+/// Remove the value at `path` from `metadata`.
+///
+/// Returns: The value that was removed.
+pub fn unset_path(metadata: &mut Value, path: &str) -> Result<Value> {
+    let keys = parse_path(path)?;
+    let (last, parents) = keys.split_last().unwrap();
+
+    let mut node = metadata;
+    for key in parents {
+        node = child_mut(node, key, false).map_err(|_| not_found(path))?;
+    }
+
+    match node {
+        Value::Sequence(seq) => {
+            let index = parse_index(last)?;
+            if index < seq.len() {
+                Ok(seq.remove(index))
+            } else {
+                Err(not_found(path))
+            }
+        }
+        Value::Mapping(m) => m
+            .remove(&Value::String(last.to_string()))
+            .ok_or_else(|| not_found(path)),
+        _ => Err(not_found(path)),
+    }
+}
+
+fn parse_path(path: &str) -> Result<Vec<&str>> {
+    let keys: Vec<&str> = path.split('.').collect();
+    if keys.iter().any(|k| k.is_empty()) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Invalid metadata path: {:?}", path),
+        ));
+    }
+    Ok(keys)
+}
+
+fn parse_index(key: &str) -> Result<usize> {
+    key.parse().map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("Expected a sequence index, got {:?}", key),
+        )
+    })
+}
+
+/// The child of `node` named `key`, optionally creating it.
+fn child_mut<'a>(node: &'a mut Value, key: &str, create: bool) -> Result<&'a mut Value> {
+    if create && node.is_null() {
+        *node = Value::Mapping(serde_yaml::Mapping::new());
+    }
+
+    match node {
+        Value::Sequence(seq) => {
+            let index = parse_index(key)?;
+            seq.get_mut(index).ok_or_else(|| not_found(key))
+        }
+        Value::Mapping(m) => {
+            let key = Value::String(key.to_string());
+            if create && !m.contains_key(&key) {
+                m.insert(key.clone(), Value::Null);
+            }
+            m.get_mut(&key)
+                .ok_or_else(|| not_found(key.as_str().unwrap_or_default()))
+        }
+        _ => Err(not_a_container(key)),
+    }
+}
+
+fn not_found(path: &str) -> Error {
+    Error::new(ErrorKind::NotFound, format!("No metadata at {}", path))
+}
+
+fn not_a_container(path: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidInput,
+        format!("Metadata at {} is not a mapping or sequence", path),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml(text: &str) -> Value {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn set_nested_keys() {
+        let mut metadata = yaml("{meta: data}");
+        set_path(&mut metadata, "review.status", yaml("done")).unwrap();
+        assert_eq!(metadata, yaml("{meta: data, review: {status: done}}"));
+    }
+
+    #[test]
+    fn set_sequence_elements() {
+        let mut metadata = yaml("{tags: [a, b]}");
+        set_path(&mut metadata, "tags.1", yaml("c")).unwrap();
+        set_path(&mut metadata, "tags.2", yaml("d")).unwrap();
+        assert_eq!(metadata, yaml("{tags: [a, c, d]}"));
+
+        let err = set_path(&mut metadata, "tags.5", yaml("e")).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn set_through_scalar_fails() {
+        let mut metadata = yaml("{meta: data}");
+        let err = set_path(&mut metadata, "meta.x", yaml("1")).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn unset_removes_values() {
+        let mut metadata = yaml("{meta: data, tags: [a, b]}");
+        assert_eq!(unset_path(&mut metadata, "tags.0").unwrap(), yaml("a"));
+        assert_eq!(unset_path(&mut metadata, "meta").unwrap(), yaml("data"));
+        assert_eq!(metadata, yaml("{tags: [b]}"));
+
+        let err = unset_path(&mut metadata, "missing.key").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn empty_path_segments_are_rejected() {
+        let mut metadata = yaml("{}");
+        assert!(set_path(&mut metadata, "a..b", yaml("1")).is_err());
+        assert!(unset_path(&mut metadata, "").is_err());
+    }
+}
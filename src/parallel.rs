@@ -0,0 +1,79 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// The number of threads to use when `jobs` are asked for: `jobs` itself, or
+/// one per CPU if it's zero.
+pub fn job_count(jobs: usize) -> usize {
+    if jobs > 0 {
+        jobs
+    } else {
+        thread::available_parallelism().map_or(1, |n| n.get())
+    }
+}
+
+/// Apply `f` to each of `items`, using up to `jobs` threads (see
+/// `job_count`).
+///
+/// Returns: The results, in the same order as `items`.
+pub fn map<T, R, F>(items: &[T], jobs: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let jobs = job_count(jobs).min(items.len());
+    if jobs <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    // Each thread takes the next item until there are none left.
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                if index >= items.len() {
+                    break;
+                }
+
+                let result = f(&items[index]);
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("every item is processed"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn results_keep_their_order() {
+        let items: Vec<u64> = (0..50).collect();
+
+        // Earlier items take longer, so they finish out of order.
+        let results = map(&items, 4, |n| {
+            thread::sleep(Duration::from_millis(50 - n));
+            n * 2
+        });
+
+        assert_eq!(results, (0..50).map(|n| n * 2).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn zero_jobs_means_one_per_cpu() {
+        assert!(job_count(0) >= 1);
+        assert_eq!(job_count(3), 3);
+    }
+}
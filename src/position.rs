@@ -0,0 +1,367 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// A location in a text, as specified by a user.
+///
+/// Lines and columns are 1-based and columns are measured in characters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Position {
+    Offset(usize),
+    LineCol(usize, usize),
+}
+
+impl Position {
+    /// Resolve the position to a character offset in `text`.
+    ///
+    /// Returns `None` if the position does not exist in `text`.
+    pub fn to_offset(&self, text: &str) -> Option<usize> {
+        match *self {
+            Position::Offset(offset) => {
+                if offset <= text.chars().count() {
+                    Some(offset)
+                } else {
+                    None
+                }
+            }
+            Position::LineCol(line, column) => line_col_to_offset(text, line, column),
+        }
+    }
+}
+
+impl FromStr for Position {
+    type Err = String;
+
+    /// Parse either a plain offset ("123") or a line and column ("3:5").
+    fn from_str(s: &str) -> Result<Position, String> {
+        let parse_number = |n: &str| {
+            n.parse::<usize>()
+                .map_err(|_| format!("Invalid position: {}", s))
+        };
+
+        let parts: Vec<&str> = s.split(':').collect();
+        match parts.as_slice() {
+            [offset] => Ok(Position::Offset(parse_number(offset)?)),
+            [line, column] => {
+                let line = parse_number(line)?;
+                let column = parse_number(column)?;
+                if line == 0 || column == 0 {
+                    return Err(format!("Lines and columns start at 1: {}", s));
+                }
+                Ok(Position::LineCol(line, column))
+            }
+            _ => Err(format!("Invalid position: {}", s)),
+        }
+    }
+}
+
+/// A span of text in a file, given in terms of lines.
+///
+/// Lines and columns are 1-based and columns are measured in characters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Location {
+    /// A line and column. The width of the span must be given separately.
+    LineCol(usize, usize),
+
+    /// A whole line, not including its line terminator.
+    Line(usize),
+
+    /// An inclusive range of whole lines.
+    Lines(usize, usize),
+}
+
+impl Location {
+    /// Split a "file:line:col", "file:line" or "file:start-end" spec into the
+    /// file and the location.
+    ///
+    /// If `spec` has no location suffix, the whole of `spec` is the file.
+    pub fn split(spec: &str) -> (&str, Option<Location>) {
+        let parse_number = |n: &str| n.parse::<usize>().ok().filter(|n| *n > 0);
+
+        let mut parts = spec.rsplitn(3, ':');
+        let last = parts.next();
+        let middle = parts.next();
+        let first = parts.next();
+
+        if let (Some(file), Some(line), Some(column)) = (first, middle, last) {
+            if let (Some(line), Some(column)) = (parse_number(line), parse_number(column)) {
+                return (file, Some(Location::LineCol(line, column)));
+            }
+        }
+
+        if let Some(index) = spec.rfind(':') {
+            let (file, suffix) = (&spec[..index], &spec[index + 1..]);
+            let mut bounds = suffix.splitn(2, '-');
+            match (bounds.next().and_then(parse_number), bounds.next().map(parse_number)) {
+                (Some(line), None) => return (file, Some(Location::Line(line))),
+                (Some(first), Some(Some(last))) if first <= last => {
+                    return (file, Some(Location::Lines(first, last)))
+                }
+                _ => {}
+            }
+        }
+
+        (spec, None)
+    }
+
+    /// Resolve the location to a `(offset, width)` span of characters in
+    /// `text`.
+    ///
+    /// `width` is required for `LineCol` locations and must not be given for
+    /// the others. Returns `None` if the location doesn't exist in `text` or
+    /// `width` is given inappropriately.
+    pub fn to_span(&self, text: &str, width: Option<usize>) -> Option<(usize, usize)> {
+        match (*self, width) {
+            (Location::LineCol(line, column), Some(width)) => {
+                line_col_to_offset(text, line, column).map(|offset| (offset, width))
+            }
+            (Location::Line(line), None) => line_span(text, line, line),
+            (Location::Lines(first, last), None) => line_span(text, first, last),
+            _ => None,
+        }
+    }
+}
+
+/// The `(offset, width)` span of characters covering lines `first` through
+/// `last` (inclusive, 1-based) of `text`.
+///
+/// The span doesn't include the terminator of the last line.
+pub fn line_span(text: &str, first: usize, last: usize) -> Option<(usize, usize)> {
+    if first == 0 || last < first {
+        return None;
+    }
+
+    let start = line_col_to_offset(text, first, 1)?;
+    let last_line_start = line_col_to_offset(text, last, 1)?;
+    let last_line_length = text.split('\n').nth(last - 1)?.chars().count();
+
+    Some((start, last_line_start + last_line_length - start))
+}
+
+/// Convert a 1-based line and column into a character offset in `text`.
+///
+/// The column may point one past the last character of the line (i.e. at the
+/// line terminator). Returns `None` if the line or column does not exist.
+pub fn line_col_to_offset(text: &str, line: usize, column: usize) -> Option<usize> {
+    if line == 0 || column == 0 {
+        return None;
+    }
+
+    let mut offset = 0;
+    for (index, line_text) in text.split('\n').enumerate() {
+        let line_length = line_text.chars().count();
+        if index + 1 == line {
+            return if column <= line_length + 1 {
+                Some(offset + column - 1)
+            } else {
+                None
+            };
+        }
+
+        // +1 for the newline
+        offset += line_length + 1;
+    }
+
+    None
+}
+
+/// Convert a character offset in `text` into a 1-based line and column.
+///
+/// Returns `None` if `offset` is past the end of `text`.
+pub fn offset_to_line_col(text: &str, offset: usize) -> Option<(usize, usize)> {
+    let mut line = 1;
+    let mut column = 1;
+    let mut chars = text.chars();
+
+    for _ in 0..offset {
+        match chars.next() {
+            Some('\n') => {
+                line += 1;
+                column = 1;
+            }
+            Some(_) => column += 1,
+            None => return None,
+        }
+    }
+
+    Some((line, column))
+}
+
+/// A unit in which offsets into a text are measured.
+///
+/// Anchors measure offsets and widths in characters (Unicode scalar values),
+/// but other tools count UTF-8 bytes or, like many editor protocols, UTF-16
+/// code units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OffsetUnit {
+    Byte,
+    Char,
+    Utf16,
+}
+
+impl OffsetUnit {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            OffsetUnit::Byte => "byte",
+            OffsetUnit::Char => "char",
+            OffsetUnit::Utf16 => "utf-16",
+        }
+    }
+
+    /// The length of `c` in this unit.
+    fn char_len(&self, c: char) -> usize {
+        match *self {
+            OffsetUnit::Byte => c.len_utf8(),
+            OffsetUnit::Char => 1,
+            OffsetUnit::Utf16 => c.len_utf16(),
+        }
+    }
+
+    /// Convert `offset`, measured in this unit, into a character offset in
+    /// `text`.
+    ///
+    /// Returns `None` if `offset` is past the end of `text` or falls inside
+    /// a character.
+    pub fn to_char_offset(&self, text: &str, offset: usize) -> Option<usize> {
+        let mut position = 0;
+        for (index, c) in text.chars().enumerate() {
+            if position == offset {
+                return Some(index);
+            } else if position > offset {
+                return None;
+            }
+            position += self.char_len(c);
+        }
+
+        if position == offset {
+            Some(text.chars().count())
+        } else {
+            None
+        }
+    }
+
+    /// Convert the character offset `offset` in `text` into this unit.
+    ///
+    /// Returns `None` if `offset` is past the end of `text`.
+    pub fn from_char_offset(&self, text: &str, offset: usize) -> Option<usize> {
+        let mut chars = text.chars();
+        let mut position = 0;
+        for _ in 0..offset {
+            position += self.char_len(chars.next()?);
+        }
+        Some(position)
+    }
+}
+
+impl fmt::Display for OffsetUnit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for OffsetUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OffsetUnit, String> {
+        match s {
+            "byte" => Ok(OffsetUnit::Byte),
+            "char" => Ok(OffsetUnit::Char),
+            "utf-16" => Ok(OffsetUnit::Utf16),
+            _ => Err(format!("Unknown offset unit: {}", s)),
+        }
+    }
+}
+
+/// Convert `offset` in `text` from one unit into another.
+///
+/// Returns `None` if `offset` is past the end of `text` or falls inside a
+/// character.
+pub fn convert_offset(text: &str, offset: usize, from: OffsetUnit, to: OffsetUnit) -> Option<usize> {
+    from.to_char_offset(text, offset)
+        .and_then(|offset| to.from_char_offset(text, offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "def func():\n    x = 1\n    return x";
+
+    #[test]
+    fn parse_positions() {
+        assert_eq!("19".parse(), Ok(Position::Offset(19)));
+        assert_eq!("3:5".parse(), Ok(Position::LineCol(3, 5)));
+        assert!("0:5".parse::<Position>().is_err());
+        assert!("a:b".parse::<Position>().is_err());
+        assert!("1:2:3".parse::<Position>().is_err());
+    }
+
+    #[test]
+    fn line_col_round_trip() {
+        for offset in 0..TEXT.chars().count() + 1 {
+            let (line, column) = offset_to_line_col(TEXT, offset).unwrap();
+            assert_eq!(line_col_to_offset(TEXT, line, column), Some(offset));
+        }
+    }
+
+    #[test]
+    fn split_locations() {
+        assert_eq!(Location::split("a.py"), ("a.py", None));
+        assert_eq!(Location::split("a.py:3"), ("a.py", Some(Location::Line(3))));
+        assert_eq!(Location::split("a.py:3:5"), ("a.py", Some(Location::LineCol(3, 5))));
+        assert_eq!(Location::split("a.py:2-3"), ("a.py", Some(Location::Lines(2, 3))));
+        assert_eq!(Location::split("C:/a.py:3"), ("C:/a.py", Some(Location::Line(3))));
+        assert_eq!(Location::split("a.py:3-2"), ("a.py:3-2", None));
+        assert_eq!(Location::split("a.py:0"), ("a.py:0", None));
+    }
+
+    #[test]
+    fn resolve_spans() {
+        assert_eq!(Location::Line(2).to_span(TEXT, None), Some((12, 9)));
+        assert_eq!(Location::Lines(1, 2).to_span(TEXT, None), Some((0, 21)));
+        assert_eq!(Location::Line(3).to_span(TEXT, None), Some((22, 12)));
+        assert_eq!(Location::LineCol(2, 5).to_span(TEXT, Some(1)), Some((16, 1)));
+        assert_eq!(Location::LineCol(2, 5).to_span(TEXT, None), None);
+        assert_eq!(Location::Line(2).to_span(TEXT, Some(3)), None);
+        assert_eq!(Location::Line(4).to_span(TEXT, None), None);
+    }
+
+    #[test]
+    fn line_col_out_of_range() {
+        assert_eq!(line_col_to_offset(TEXT, 2, 1), Some(12));
+        assert_eq!(line_col_to_offset(TEXT, 4, 1), None);
+        assert_eq!(line_col_to_offset(TEXT, 1, 13), None);
+        assert_eq!(offset_to_line_col(TEXT, 100), None);
+    }
+
+    // "é" is two bytes and one UTF-16 code unit; "𝄞" is four bytes and two
+    // UTF-16 code units.
+    const MULTIBYTE: &str = "aé𝄞b";
+
+    #[test]
+    fn offsets_convert_between_units() {
+        let offsets = [(0, 0, 0), (1, 1, 1), (2, 3, 2), (3, 7, 4), (4, 8, 5)];
+        for &(chars, bytes, utf16) in offsets.iter() {
+            assert_eq!(convert_offset(MULTIBYTE, chars, OffsetUnit::Char, OffsetUnit::Byte), Some(bytes));
+            assert_eq!(convert_offset(MULTIBYTE, chars, OffsetUnit::Char, OffsetUnit::Utf16), Some(utf16));
+            assert_eq!(convert_offset(MULTIBYTE, bytes, OffsetUnit::Byte, OffsetUnit::Char), Some(chars));
+            assert_eq!(convert_offset(MULTIBYTE, utf16, OffsetUnit::Utf16, OffsetUnit::Byte), Some(bytes));
+        }
+    }
+
+    #[test]
+    fn offsets_inside_characters_are_rejected() {
+        assert_eq!(OffsetUnit::Byte.to_char_offset(MULTIBYTE, 2), None);
+        assert_eq!(OffsetUnit::Byte.to_char_offset(MULTIBYTE, 5), None);
+        assert_eq!(OffsetUnit::Utf16.to_char_offset(MULTIBYTE, 3), None);
+        assert_eq!(OffsetUnit::Byte.to_char_offset(MULTIBYTE, 9), None);
+        assert_eq!(OffsetUnit::Char.from_char_offset(MULTIBYTE, 5), None);
+    }
+
+    #[test]
+    fn parse_offset_units() {
+        for unit in [OffsetUnit::Byte, OffsetUnit::Char, OffsetUnit::Utf16].iter() {
+            assert_eq!(unit.name().parse(), Ok(*unit));
+        }
+        assert!("word".parse::<OffsetUnit>().is_err());
+    }
+}
@@ -0,0 +1,374 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use alignment::align::Align;
+use anchor::Anchor;
+use file_io::read_file;
+use updating::{update_with_confidence, UpdateError, UpdateOptions};
+
+/// The length of the k-grams that fingerprint a text.
+const GRAM_LENGTH: usize = 5;
+
+/// The fraction of the k-grams of an anchor's context that a file must
+/// contain for the context to be aligned with it.
+const MIN_SHARED_GRAMS: f32 = 0.3;
+
+/// The least confidence with which an anchor is moved to another file. This
+/// is higher than `UpdateOptions::threshold`'s default, since a poor match in
+/// an unrelated file is more likely than one in the anchor's own file.
+const MIN_RELOCATION_CONFIDENCE: f32 = 0.8;
+
+/// Directories of build output and dependencies, which aren't searched.
+const BUILD_DIRS: &[&str] = &["target", "node_modules", "__pycache__"];
+
+/// The files under a repository root that anchors can be relocated to.
+///
+/// The files are found, read and fingerprinted the first time an anchor in
+/// an encoding needs them, and the results are shared by every anchor after
+/// that, so one of these should be shared by all the anchors being updated.
+#[derive(Debug)]
+pub struct Candidates {
+    root: PathBuf,
+    extensions: Option<HashSet<Option<OsString>>>,
+    fingerprints: Mutex<HashMap<String, Arc<Vec<Candidate>>>>,
+}
+
+#[derive(Debug)]
+struct Candidate {
+    path: PathBuf,
+    prints: HashSet<u64>,
+}
+
+impl Candidates {
+    /// The files under `root`, except hidden ones, those in hidden
+    /// directories such as the spor directory, and those in build
+    /// directories.
+    pub fn new(root: &Path) -> Candidates {
+        Candidates {
+            root: root.to_path_buf(),
+            extensions: None,
+            fingerprints: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Only consider files with one of `extensions`, where `None` means files
+    /// without one.
+    pub fn with_extensions<'a, I>(mut self, extensions: I) -> Candidates
+    where
+        I: IntoIterator<Item = Option<&'a OsStr>>,
+    {
+        self.extensions = Some(
+            extensions
+                .into_iter()
+                .map(|extension| extension.map(OsStr::to_os_string))
+                .collect(),
+        );
+        self
+    }
+
+    /// The candidate files that are text in `encoding`, with their
+    /// fingerprints.
+    fn fingerprinted(&self, encoding: &str) -> io::Result<Arc<Vec<Candidate>>> {
+        // The lock is held while fingerprinting so that other threads wait
+        // for the result rather than duplicating the work.
+        let mut fingerprints = self.fingerprints.lock().unwrap();
+        if let Some(candidates) = fingerprints.get(encoding) {
+            return Ok(candidates.clone());
+        }
+
+        let candidates: Vec<Candidate> = source_files(&self.root)?
+            .into_iter()
+            .filter(|path| match self.extensions {
+                Some(ref extensions) => extensions.contains(&path.extension().map(OsStr::to_os_string)),
+                None => true,
+            })
+            .filter_map(|path| {
+                // Binary files and the like are skipped.
+                let text = read_file(&path, encoding).ok()?;
+                if text.contains('\0') {
+                    return None;
+                }
+                let prints = fingerprint(&text);
+                Some(Candidate { path, prints })
+            })
+            .collect();
+
+        let candidates = Arc::new(candidates);
+        fingerprints.insert(encoding.to_string(), candidates.clone());
+        Ok(candidates)
+    }
+}
+
+/// Like `update_with_confidence`, but if the anchor's file no longer exists,
+/// look for the anchor in the other `candidates`. See `relocate`.
+///
+/// Returns `UpdateError::Orphaned` if the file is missing and the anchor
+/// isn't found anywhere else.
+pub fn update_or_relocate(
+    anchor: &Anchor,
+    candidates: &Candidates,
+    align: &Align,
+    options: &UpdateOptions,
+) -> Result<(Anchor, f32), UpdateError> {
+    match update_with_confidence(anchor, align, options) {
+        Err(UpdateError::Io(io::ErrorKind::NotFound, _)) if !anchor.file_path().exists() => {
+            relocate(anchor, candidates, align, options)?.ok_or(UpdateError::Orphaned)
+        }
+        result => result,
+    }
+}
+
+/// Find the anchor in one of `candidates` other than its own file, e.g.
+/// because its file has been renamed or moved.
+///
+/// Files with the same extension as the anchor's file are searched first, and
+/// the others only if none of those match. Only files sharing enough of the
+/// context's fingerprint are aligned with it. Of the files that match with
+/// enough confidence, the best match is used. That's at least
+/// `MIN_RELOCATION_CONFIDENCE`, whatever `options.threshold` says.
+///
+/// Returns: The anchor in its new file and the confidence of the match, or
+/// `None` if no file matches.
+pub fn relocate(
+    anchor: &Anchor,
+    candidates: &Candidates,
+    align: &Align,
+    options: &UpdateOptions,
+) -> Result<Option<(Anchor, f32)>, UpdateError> {
+    let context_prints = fingerprint(&anchor.context().full_text());
+    let options = UpdateOptions {
+        threshold: options.threshold.max(MIN_RELOCATION_CONFIDENCE),
+        ..options.clone()
+    };
+
+    let fingerprinted = candidates.fingerprinted(anchor.encoding())?;
+    let extension = anchor.file_path().extension();
+    let (same_extension, others): (Vec<&Candidate>, Vec<&Candidate>) = fingerprinted
+        .iter()
+        .filter(|candidate| candidate.path != *anchor.file_path())
+        .filter(|candidate| shared_grams(&context_prints, &candidate.prints) >= MIN_SHARED_GRAMS)
+        .partition(|candidate| candidate.path.extension() == extension);
+
+    for candidates in &[same_extension, others] {
+        let mut best: Option<(Anchor, f32)> = None;
+
+        for candidate in candidates {
+            let moved = anchor.clone().with_file_path(&candidate.path);
+            match update_with_confidence(&moved, align, &options) {
+                Ok((updated, confidence)) => {
+                    if best.as_ref().is_none_or(|(_, best)| confidence > *best) {
+                        best = Some((updated, confidence));
+                    }
+                }
+                Err(UpdateError::NoAlignments)
+                | Err(UpdateError::InvalidAlignment)
+                | Err(UpdateError::LowConfidence { .. }) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        if best.is_some() {
+            return Ok(best);
+        }
+    }
+
+    Ok(None)
+}
+
+/// All files under `root`, in order, except hidden ones, those in hidden
+/// directories such as the spor directory, and those in build directories.
+/// Those directories aren't descended into.
+fn source_files(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut entries: Vec<(PathBuf, bool)> = fs::read_dir(root)?
+        .map(|entry| {
+            let entry = entry?;
+            Ok((entry.path(), entry.file_type()?.is_dir()))
+        })
+        .collect::<io::Result<_>>()?;
+    entries.sort();
+
+    let mut files = Vec::new();
+    for (path, is_dir) in entries {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if name.starts_with('.') {
+            continue;
+        }
+
+        if !is_dir {
+            if path.is_file() {
+                files.push(path);
+            }
+        } else if !BUILD_DIRS.contains(&name.as_ref()) {
+            files.extend(source_files(&path)?);
+        }
+    }
+    Ok(files)
+}
+
+/// The hashes of every k-gram in `text`, ignoring whitespace and case so that
+/// reformatted text still has much the same fingerprint.
+fn fingerprint(text: &str) -> HashSet<u64> {
+    let chars: Vec<char> = text
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect();
+
+    // A text shorter than a k-gram is a k-gram of its own.
+    chars
+        .chunks(GRAM_LENGTH)
+        .take(1)
+        .chain(chars.windows(GRAM_LENGTH))
+        .map(|gram| {
+            let mut hasher = DefaultHasher::new();
+            gram.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect()
+}
+
+/// The fraction of the k-grams in `prints` that are also in `other`.
+fn shared_grams(prints: &HashSet<u64>, other: &HashSet<u64>) -> f32 {
+    if prints.is_empty() {
+        return 1.0;
+    }
+    prints.intersection(other).count() as f32 / prints.len() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate serde_yaml;
+    extern crate tempdir;
+
+    use super::*;
+    use alignment::smith_waterman::align;
+    use anchor::Context;
+    use std::fs;
+
+    fn make_anchor(path: &Path, text: &str, offset: usize, width: usize) -> Anchor {
+        fs::write(path, text).unwrap();
+        let context = Context::new(text, offset, width, 10).unwrap();
+        Anchor::new(path, context, serde_yaml::Value::Null, "utf-8".to_string()).unwrap()
+    }
+
+    #[test]
+    fn fingerprints_ignore_formatting() {
+        let prints = fingerprint("def func(a, b):\n    return a + b\n");
+        assert_eq!(shared_grams(&prints, &fingerprint("def func(a,b): RETURN a+b")), 1.0);
+        assert!(shared_grams(&prints, &fingerprint("class Widget:\n    pass\n")) < MIN_SHARED_GRAMS);
+        assert_eq!(fingerprint("ab").len(), 1);
+    }
+
+    #[test]
+    fn renamed_file_is_followed() {
+        let dir = tempdir::TempDir::new("spor_relocation_tests").unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let text = "import os\n\ndef func():\n    return os.getcwd()\n";
+        let anchor = make_anchor(&root.join("old.py"), text, 15, 4);
+
+        fs::remove_file(root.join("old.py")).unwrap();
+        fs::create_dir(root.join("pkg")).unwrap();
+        fs::write(root.join("pkg/new.py"), format!("# moved\n{}", text)).unwrap();
+        fs::write(root.join("unrelated.py"), "class Widget:\n    pass\n").unwrap();
+
+        let (updated, confidence) =
+            update_or_relocate(&anchor, &Candidates::new(&root), &align, &UpdateOptions::default()).unwrap();
+        assert_eq!(updated.file_path(), &root.join("pkg/new.py"));
+        assert_eq!(updated.context().offset(), 23);
+        assert_eq!(updated.context().topic(), "func");
+        assert_eq!(confidence, 1.0);
+    }
+
+    #[test]
+    fn same_extension_is_preferred() {
+        let dir = tempdir::TempDir::new("spor_relocation_tests").unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let text = "def func():\n    return 42\n";
+        let anchor = make_anchor(&root.join("old.py"), text, 4, 4);
+
+        fs::remove_file(root.join("old.py")).unwrap();
+        fs::write(root.join("a.txt"), text).unwrap();
+        fs::write(root.join("b.py"), "def func():\n    return 43\n").unwrap();
+
+        let (updated, _) = relocate(&anchor, &Candidates::new(&root), &align, &UpdateOptions::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.file_path(), &root.join("b.py"));
+    }
+
+    #[test]
+    fn hidden_files_are_not_searched() {
+        let dir = tempdir::TempDir::new("spor_relocation_tests").unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let text = "def func():\n    return 42\n";
+        let anchor = make_anchor(&root.join("old.py"), text, 4, 4);
+
+        fs::remove_file(root.join("old.py")).unwrap();
+        fs::create_dir(root.join(".git")).unwrap();
+        fs::write(root.join(".git/old.py"), text).unwrap();
+        fs::write(root.join(".hidden.py"), text).unwrap();
+
+        let err = update_or_relocate(&anchor, &Candidates::new(&root), &align, &UpdateOptions::default()).unwrap_err();
+        assert_eq!(err, UpdateError::Orphaned);
+    }
+
+    #[test]
+    fn build_and_binary_files_are_not_searched() {
+        let dir = tempdir::TempDir::new("spor_relocation_tests").unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let text = "def func():\n    return 42\n";
+        let anchor = make_anchor(&root.join("old.py"), text, 4, 4);
+
+        fs::remove_file(root.join("old.py")).unwrap();
+        fs::create_dir(root.join("target")).unwrap();
+        fs::write(root.join("target/old.py"), text).unwrap();
+        fs::write(root.join("old.pyc"), format!("\0{}", text)).unwrap();
+
+        let err = update_or_relocate(&anchor, &Candidates::new(&root), &align, &UpdateOptions::default()).unwrap_err();
+        assert_eq!(err, UpdateError::Orphaned);
+    }
+
+    #[test]
+    fn candidates_are_shared_and_limited_to_extensions() {
+        let dir = tempdir::TempDir::new("spor_relocation_tests").unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let text = "def func():\n    return 42\n";
+        let anchor = make_anchor(&root.join("old.py"), text, 4, 4);
+
+        fs::remove_file(root.join("old.py")).unwrap();
+        fs::write(root.join("new.txt"), text).unwrap();
+        let candidates = Candidates::new(&root).with_extensions(vec![Some(OsStr::new("py"))]);
+        let err = update_or_relocate(&anchor, &candidates, &align, &UpdateOptions::default()).unwrap_err();
+        assert_eq!(err, UpdateError::Orphaned);
+
+        // The files are only found once.
+        fs::write(root.join("new.py"), text).unwrap();
+        let err = update_or_relocate(&anchor, &candidates, &align, &UpdateOptions::default()).unwrap_err();
+        assert_eq!(err, UpdateError::Orphaned);
+
+        let (updated, _) = update_or_relocate(&anchor, &Candidates::new(&root), &align, &UpdateOptions::default()).unwrap();
+        assert_eq!(updated.file_path(), &root.join("new.py"));
+    }
+
+    #[test]
+    fn existing_files_are_not_relocated() {
+        let dir = tempdir::TempDir::new("spor_relocation_tests").unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let text = "def func():\n    return 42\n";
+        let anchor = make_anchor(&root.join("old.py"), text, 4, 4);
+
+        fs::write(root.join("old.py"), "class Widget:\n    pass\n").unwrap();
+        fs::write(root.join("new.py"), text).unwrap();
+
+        match update_or_relocate(&anchor, &Candidates::new(&root), &align, &UpdateOptions::default()) {
+            Ok(_) | Err(UpdateError::Orphaned) => panic!("anchor should be left in its file"),
+            Err(_) => {}
+        }
+    }
+}
@@ -0,0 +1,93 @@
+extern crate serde_yaml;
+
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use super::serialization::{write_atomically, FORMAT_VERSION};
+
+/// The kinds of anchor storage a repository can use.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StoreKind {
+    /// One YAML file per anchor. See `DirStore`.
+    #[default]
+    Directory,
+
+    /// All anchors in one YAML document. See `SingleFileStore`.
+    SingleFile,
+}
+
+impl FromStr for StoreKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<StoreKind, String> {
+        match s {
+            "directory" => Ok(StoreKind::Directory),
+            "single-file" => Ok(StoreKind::SingleFile),
+            _ => Err(format!("Unknown store kind: {}", s)),
+        }
+    }
+}
+
+/// Repository-wide settings, stored in the spor directory.
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct Config {
+    /// The anchor format version that all anchors in the repository have
+    /// been migrated to.
+    pub format_version: u64,
+
+    /// How anchors are stored.
+    #[serde(default)]
+    pub store: StoreKind,
+
+    /// The name of the alignment algorithm used to update anchors, if not
+    /// the default. See `alignment::registry`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub algorithm: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            format_version: FORMAT_VERSION,
+            store: StoreKind::default(),
+            algorithm: None,
+        }
+    }
+}
+
+impl Config {
+    /// Read the config for the repository in `spor_dir`.
+    ///
+    /// Repositories created before spor had a config file get a config with
+    /// format version 1.
+    pub fn load(spor_dir: &Path) -> io::Result<Config> {
+        let f = match File::open(config_path(spor_dir)) {
+            Ok(f) => f,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(Config {
+                    format_version: 1,
+                    store: StoreKind::Directory,
+                    algorithm: None,
+                })
+            }
+            Err(err) => return Err(err),
+        };
+
+        serde_yaml::from_reader(io::BufReader::new(f))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, spor_dir: &Path) -> io::Result<()> {
+        write_atomically(&config_path(spor_dir), |writer| {
+            serde_yaml::to_writer(writer, self)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        })
+    }
+}
+
+fn config_path(spor_dir: &Path) -> PathBuf {
+    spor_dir.join("config")
+}
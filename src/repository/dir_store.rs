@@ -0,0 +1,398 @@
+use std::collections::BTreeMap;
+use std::fs::{remove_file, rename, DirBuilder};
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anchor::Anchor;
+use super::index::{file_stamp, FileStamp, Index, IndexEntry};
+use super::iteration::{anchor_files, QUARANTINE_DIR};
+use super::lock::{RepositoryLock, LOCK_TIMEOUT};
+use super::repository::AnchorId;
+use super::serialization::{format_version, read_anchor, read_mapping, write_anchor, FORMAT_VERSION};
+use super::store::{AnchorLoadError, AnchorStore};
+
+/// Stores each anchor in its own YAML file in the spor directory.
+///
+/// An index of anchored files is kept alongside the anchor files so that
+/// per-file queries don't need to read every anchor.
+#[derive(Debug)]
+pub struct DirStore {
+    repo_root: PathBuf,
+    spor_dir: PathBuf,
+}
+
+impl DirStore {
+    /// A store for the repository at `repo_root` keeping its data in the
+    /// (absolute) directory `spor_dir`.
+    pub fn new(repo_root: &Path, spor_dir: &Path) -> DirStore {
+        DirStore {
+            repo_root: repo_root.to_path_buf(),
+            spor_dir: spor_dir.to_path_buf(),
+        }
+    }
+
+    /// Absolute path to the data file for `anchor_id`.
+    fn anchor_path(&self, anchor_id: &AnchorId) -> PathBuf {
+        let file_name = format!("{}.yml", anchor_id);
+        let path = self.spor_dir.join(file_name);
+        assert!(path.is_absolute());
+        path
+    }
+
+    /// Absolute path to the anchor index.
+    fn index_path(&self) -> PathBuf {
+        self.spor_dir.join("index")
+    }
+
+    /// Take the repository lock.
+    ///
+    /// Every mutation must hold this lock. It's not reentrant, so only the
+    /// public mutators take it.
+    fn lock(&self) -> io::Result<RepositoryLock> {
+        RepositoryLock::acquire(&self.spor_dir, LOCK_TIMEOUT)
+    }
+
+    /// The anchor index, building it if it doesn't exist yet and bringing it
+    /// up to date if it's stale.
+    ///
+    /// This is for read-only operations. It takes the lock only if it has to
+    /// write a new index.
+    fn index(&self) -> io::Result<Index> {
+        if let Some(index) = Index::load(&self.index_path())? {
+            if index.covers(&self.anchor_stamps()?) {
+                return Ok(index);
+            }
+        }
+
+        let _lock = self.lock()?;
+        self.load_or_build_index()
+    }
+
+    /// The anchor index, building it if it doesn't exist yet and bringing it
+    /// up to date if it's stale, and saving it if it changed.
+    ///
+    /// The index is stale if anchor files have been added, removed or
+    /// rewritten other than through the store, e.g. by hand or by a version
+    /// control checkout. Only those anchor files are read again.
+    ///
+    /// The caller must hold the repository lock.
+    fn load_or_build_index(&self) -> io::Result<Index> {
+        let mut index = Index::load(&self.index_path())?.unwrap_or_default();
+        let stamps = self.anchor_stamps()?;
+        if !index.covers(&stamps) {
+            self.refresh(&mut index, &stamps)?;
+            index.save(&self.index_path())?;
+        }
+        Ok(index)
+    }
+
+    fn build_index(&self) -> io::Result<Index> {
+        let mut index = Index::default();
+        self.refresh(&mut index, &self.anchor_stamps()?)?;
+        Ok(index)
+    }
+
+    /// Bring `index` up to date with the anchor files with `stamps`, reading
+    /// those whose stamps differ from the ones in the index.
+    fn refresh(&self, index: &mut Index, stamps: &BTreeMap<AnchorId, FileStamp>) -> io::Result<()> {
+        let removed: Vec<AnchorId> = index
+            .anchor_ids()
+            .filter(|anchor_id| !stamps.contains_key(*anchor_id))
+            .cloned()
+            .collect();
+        for anchor_id in removed {
+            index.remove(&anchor_id);
+        }
+
+        for (anchor_id, anchor_path) in anchor_files(&self.spor_dir).into_iter().filter_map(Result::ok) {
+            let stamp = match stamps.get(&anchor_id) {
+                Some(stamp) => stamp,
+                None => continue,
+            };
+            if index.stamp(&anchor_id) == Some(stamp) {
+                continue;
+            }
+
+            // Anchor files that can't be read are recorded without an entry,
+            // so that they aren't read again until they change.
+            index.remove(&anchor_id);
+            if let Ok(anchor) = read_anchor(&anchor_path, &self.repo_root) {
+                self.index_anchor(index, &anchor_id, &anchor)?;
+            }
+            index.set_stamp(&anchor_id, *stamp);
+        }
+
+        Ok(())
+    }
+
+    /// The stamps of the anchor files in the spor directory.
+    fn anchor_stamps(&self) -> io::Result<BTreeMap<AnchorId, FileStamp>> {
+        anchor_files(&self.spor_dir)
+            .into_iter()
+            .filter_map(Result::ok)
+            .map(|(anchor_id, anchor_path)| Ok((anchor_id, file_stamp(&anchor_path)?)))
+            .collect()
+    }
+
+    /// Record `anchor` in `index`.
+    fn index_anchor(&self, index: &mut Index, anchor_id: &AnchorId, anchor: &Anchor) -> io::Result<()> {
+        let file_path = self.relative_path(anchor.file_path())?;
+        let entry = IndexEntry {
+            id: anchor_id.clone(),
+            offset: anchor.context().offset(),
+            width: anchor.context().topic().chars().count(),
+        };
+        index.insert(&file_path, entry);
+        Ok(())
+    }
+
+    /// Write `anchor` as `anchor_id` and index it.
+    ///
+    /// The caller must hold the repository lock.
+    fn write(&self, anchor_id: &AnchorId, anchor: &Anchor) -> io::Result<()> {
+        // The index is brought up to date first, so that only this anchor
+        // file's new stamp has to be recorded.
+        let mut index = self.load_or_build_index()?;
+        let anchor_path = self.anchor_path(anchor_id);
+        write_anchor(&anchor_path, anchor, &self.repo_root)?;
+
+        self.index_anchor(&mut index, anchor_id, anchor)?;
+        index.set_stamp(anchor_id, file_stamp(&anchor_path)?);
+        index.save(&self.index_path())
+    }
+
+    /// The path of the absolute `path` relative to the repository root.
+    fn relative_path(&self, path: &Path) -> io::Result<PathBuf> {
+        path.strip_prefix(&self.repo_root)
+            .map(PathBuf::from)
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{:?} is not within the repository", path),
+                )
+            })
+    }
+}
+
+impl AnchorStore for DirStore {
+    fn get(&self, anchor_id: &AnchorId) -> io::Result<Option<Anchor>> {
+        let path = self.anchor_path(anchor_id);
+        match read_anchor(&path, &self.repo_root) {
+            Err(err) => {
+                match err.kind() {
+                    io::ErrorKind::NotFound => {
+                        Ok(None)
+                    }
+                    _ => Err(err)
+                }
+            }
+            Ok(anchor) => Ok(Some(anchor))
+        }
+    }
+
+    fn put(&self, anchor_id: &AnchorId, anchor: &Anchor) -> io::Result<()> {
+        let _lock = self.lock()?;
+        self.write(anchor_id, anchor)
+    }
+
+    fn update(&self, anchor_id: &AnchorId, anchor: &Anchor) -> io::Result<bool> {
+        let _lock = self.lock()?;
+        if !self.anchor_path(anchor_id).exists() {
+            return Ok(false);
+        }
+        self.write(anchor_id, anchor)?;
+        Ok(true)
+    }
+
+    fn delete(&self, anchor_id: &AnchorId) -> io::Result<Option<Anchor>> {
+        let _lock = self.lock()?;
+
+        let anchor_path = self.anchor_path(anchor_id);
+        let anchor = match self.get(anchor_id)? {
+            Some(anchor) => anchor,
+            None => return Ok(None),
+        };
+
+        let mut index = self.load_or_build_index()?;
+        remove_file(&anchor_path)?;
+        if index.remove(anchor_id) {
+            index.save(&self.index_path())?;
+        }
+
+        Ok(Some(anchor))
+    }
+
+    fn try_iter<'a>(&'a self) -> Box<dyn Iterator<Item = Result<(AnchorId, Anchor), AnchorLoadError>> + 'a> {
+        Box::new(
+            anchor_files(&self.spor_dir)
+                .into_iter()
+                .rev()
+                .map(move |entry| {
+                    let (anchor_id, anchor_path) = entry?;
+                    read_anchor(&anchor_path, &self.repo_root)
+                        .map(|anchor| (anchor_id.clone(), anchor))
+                        .map_err(|cause| AnchorLoadError {
+                            path: anchor_path,
+                            anchor_id: Some(anchor_id),
+                            cause,
+                        })
+                }),
+        )
+    }
+
+    fn quarantine(&self, error: &AnchorLoadError) -> io::Result<PathBuf> {
+        let _lock = self.lock()?;
+
+        let quarantine_dir = self.spor_dir.join(QUARANTINE_DIR);
+        DirBuilder::new().recursive(true).create(&quarantine_dir)?;
+
+        let file_name = error.path.file_name().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("Not a file: {:?}", error.path))
+        })?;
+        let destination = quarantine_dir.join(file_name);
+
+        let mut index = self.load_or_build_index()?;
+        rename(&error.path, &destination)?;
+
+        if let Some(ref anchor_id) = error.anchor_id {
+            if index.remove(anchor_id) {
+                index.save(&self.index_path())?;
+            }
+        }
+
+        Ok(destination)
+    }
+
+    fn files(&self) -> io::Result<Vec<PathBuf>> {
+        let index = self.index()?;
+        Ok(index.files().map(|f| self.repo_root.join(f)).collect())
+    }
+
+    fn locations(&self, file_path: &Path) -> io::Result<Vec<(AnchorId, Range<usize>)>> {
+        let index = self.index()?;
+        let file_path = self.relative_path(file_path)?;
+
+        Ok(index
+            .entries(&file_path)
+            .iter()
+            .map(|e| (e.id.clone(), e.offset..e.offset + e.width))
+            .collect())
+    }
+
+    fn reindex(&self) -> io::Result<()> {
+        let _lock = self.lock()?;
+        let index = self.build_index()?;
+        index.save(&self.index_path())
+    }
+
+    fn migrate(&self) -> io::Result<usize> {
+        let _lock = self.lock()?;
+
+        let mut migrated = 0;
+        for entry in anchor_files(&self.spor_dir) {
+            let (_anchor_id, anchor_path) = entry.map_err(|err| {
+                io::Error::new(err.cause.kind(), err.to_string())
+            })?;
+            let in_context = |err: io::Error| {
+                io::Error::new(err.kind(), format!("{:?}: {}", anchor_path, err))
+            };
+
+            let version = read_mapping(&anchor_path)
+                .and_then(|m| format_version(&m))
+                .map_err(&in_context)?;
+
+            if version < FORMAT_VERSION {
+                let anchor = read_anchor(&anchor_path, &self.repo_root).map_err(&in_context)?;
+                write_anchor(&anchor_path, &anchor, &self.repo_root).map_err(&in_context)?;
+                migrated += 1;
+            }
+        }
+
+        let index = self.build_index()?;
+        index.save(&self.index_path())?;
+
+        Ok(migrated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use super::super::store::conformance::check_store;
+    use std::fs;
+
+    #[test]
+    fn conformance() {
+        let dir = tempdir::TempDir::new("spor_dir_store_tests").unwrap();
+        check_store(&DirStore::new(dir.path(), dir.path()), dir.path());
+    }
+
+    #[test]
+    fn broken_anchors_are_reported_and_quarantined() {
+        let dir = tempdir::TempDir::new("spor_dir_store_tests").unwrap();
+        let store = DirStore::new(dir.path(), dir.path());
+        fs::write(dir.path().join("broken.yml"), "file_path: [unclosed").unwrap();
+
+        let errors: Vec<AnchorLoadError> = store.try_iter().filter_map(Result::err).collect();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].anchor_id, Some("broken".to_string()));
+        assert_eq!(errors[0].cause.kind(), io::ErrorKind::InvalidData);
+
+        let destination = store.quarantine(&errors[0]).unwrap();
+        assert!(destination.exists());
+        assert_eq!(store.try_iter().count(), 0);
+    }
+
+    fn make_anchor(source_path: &Path, offset: usize) -> Anchor {
+        let context = ::anchor::Context::new("def func():", offset, 4, 3).unwrap();
+        Anchor::new(source_path, context, serde_yaml::Value::Null, "utf-8".to_string()).unwrap()
+    }
+
+    #[test]
+    fn anchor_files_added_directly_are_indexed() {
+        let dir = tempdir::TempDir::new("spor_dir_store_tests").unwrap();
+        let store = DirStore::new(dir.path(), dir.path());
+        let source_path = dir.path().join("source.py");
+        store.put(&"first".to_string(), &make_anchor(&source_path, 4)).unwrap();
+        assert_eq!(store.locations(&source_path).unwrap().len(), 1);
+
+        // E.g. an anchor file brought in by a version control checkout.
+        write_anchor(&store.anchor_path(&"second".to_string()), &make_anchor(&source_path, 0), dir.path()).unwrap();
+        assert_eq!(
+            store.locations(&source_path).unwrap(),
+            vec![("second".to_string(), 0..4), ("first".to_string(), 4..8)]
+        );
+        assert_eq!(store.files().unwrap(), vec![source_path.clone()]);
+
+        remove_file(store.anchor_path(&"first".to_string())).unwrap();
+        remove_file(store.anchor_path(&"second".to_string())).unwrap();
+        assert!(store.files().unwrap().is_empty());
+    }
+
+    #[test]
+    fn anchor_files_rewritten_in_place_are_reindexed() {
+        let dir = tempdir::TempDir::new("spor_dir_store_tests").unwrap();
+        let store = DirStore::new(dir.path(), dir.path());
+        let source_path = dir.path().join("source.py");
+        store.put(&"anchor".to_string(), &make_anchor(&source_path, 4)).unwrap();
+        assert_eq!(store.locations(&source_path).unwrap(), vec![("anchor".to_string(), 4..8)]);
+
+        write_anchor(&store.anchor_path(&"anchor".to_string()), &make_anchor(&source_path, 0), dir.path()).unwrap();
+        assert_eq!(store.locations(&source_path).unwrap(), vec![("anchor".to_string(), 0..4)]);
+    }
+
+    #[test]
+    fn reindex_rebuilds_the_index() {
+        let dir = tempdir::TempDir::new("spor_dir_store_tests").unwrap();
+        let store = DirStore::new(dir.path(), dir.path());
+        let source_path = dir.path().join("source.py");
+        store.put(&"anchor".to_string(), &make_anchor(&source_path, 4)).unwrap();
+
+        remove_file(store.index_path()).unwrap();
+        store.reindex().unwrap();
+        assert_eq!(store.locations(&source_path).unwrap(), vec![("anchor".to_string(), 4..8)]);
+    }
+}
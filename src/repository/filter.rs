@@ -0,0 +1,153 @@
+extern crate glob;
+
+use std::path::{Path, PathBuf};
+
+use super::repository::AnchorId;
+
+/// A file, directory or glob selecting anchored files.
+#[derive(Debug)]
+pub enum Pathspec {
+    /// A single file, which need not exist any more.
+    File(PathBuf),
+
+    /// Every file under a directory.
+    Directory(PathBuf),
+
+    /// Files whose absolute paths match a pattern. As with git pathspecs,
+    /// `*` also matches `/`.
+    Glob(glob::Pattern),
+}
+
+impl Pathspec {
+    /// Interpret `spec` relative to the directory `base`.
+    ///
+    /// `base` should be canonical, since anchors are stored with canonical
+    /// paths.
+    pub fn new(spec: &str, base: &Path) -> Result<Pathspec, String> {
+        if spec.contains(&['*', '?', '['][..]) {
+            let base = base
+                .to_str()
+                .ok_or_else(|| format!("Invalid path: {:?}", base))?;
+            let pattern = if Path::new(spec).is_absolute() {
+                spec.to_string()
+            } else {
+                format!("{}/{}", glob::Pattern::escape(base), spec)
+            };
+            return glob::Pattern::new(&pattern)
+                .map(Pathspec::Glob)
+                .map_err(|e| format!("Invalid pattern {}: {}", spec, e));
+        }
+
+        let path = base.join(spec);
+        let path = path.canonicalize().unwrap_or(path);
+        if path.is_dir() {
+            Ok(Pathspec::Directory(path))
+        } else {
+            Ok(Pathspec::File(path))
+        }
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        match self {
+            Pathspec::File(file) => path == file,
+            Pathspec::Directory(dir) => path.starts_with(dir),
+            Pathspec::Glob(pattern) => pattern.matches_path(path),
+        }
+    }
+}
+
+/// Selects anchors by the files they're in and by ID prefix.
+///
+/// An anchor is selected if its file matches any of the pathspecs and its ID
+/// starts with any of the prefixes. With no pathspecs or no prefixes, that
+/// part of the filter selects everything.
+#[derive(Debug, Default)]
+pub struct AnchorFilter {
+    pathspecs: Vec<Pathspec>,
+    id_prefixes: Vec<String>,
+}
+
+impl AnchorFilter {
+    /// A filter selecting every anchor.
+    pub fn new() -> AnchorFilter {
+        AnchorFilter::default()
+    }
+
+    pub fn with_pathspec(mut self, pathspec: Pathspec) -> AnchorFilter {
+        self.pathspecs.push(pathspec);
+        self
+    }
+
+    pub fn with_id_prefix(mut self, prefix: &str) -> AnchorFilter {
+        self.id_prefixes.push(prefix.to_string());
+        self
+    }
+
+    pub fn matches_path(&self, path: &Path) -> bool {
+        self.pathspecs.is_empty() || self.pathspecs.iter().any(|spec| spec.matches(path))
+    }
+
+    pub fn matches_id(&self, anchor_id: &AnchorId) -> bool {
+        self.id_prefixes.is_empty()
+            || self
+                .id_prefixes
+                .iter()
+                .any(|prefix| anchor_id.starts_with(prefix.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn pathspecs_are_relative_to_base() {
+        let dir = tempdir::TempDir::new("spor_filter_tests").unwrap();
+        let base = dir.path().canonicalize().unwrap();
+        fs::create_dir(base.join("src")).unwrap();
+        fs::write(base.join("src/main.py"), "").unwrap();
+
+        let file = Pathspec::new("src/main.py", &base).unwrap();
+        assert!(file.matches(&base.join("src/main.py")));
+        assert!(!file.matches(&base.join("src/other.py")));
+
+        let dir = Pathspec::new("src", &base).unwrap();
+        assert!(dir.matches(&base.join("src/main.py")));
+        assert!(!dir.matches(&base.join("main.py")));
+
+        // Deleted files can still be named.
+        let missing = Pathspec::new("gone.py", &base).unwrap();
+        assert!(missing.matches(&base.join("gone.py")));
+    }
+
+    #[test]
+    fn glob_pathspecs() {
+        let base = Path::new("/work/[repo]");
+        let spec = Pathspec::new("*.py", base).unwrap();
+        assert!(spec.matches(Path::new("/work/[repo]/main.py")));
+        assert!(spec.matches(Path::new("/work/[repo]/src/lib.py")));
+        assert!(!spec.matches(Path::new("/work/[repo]/main.rs")));
+        assert!(!spec.matches(Path::new("/work/r/main.py")));
+    }
+
+    #[test]
+    fn filters_combine_paths_and_ids() {
+        let base = Path::new("/work");
+        let everything = AnchorFilter::new();
+        assert!(everything.matches_path(Path::new("/anywhere")));
+        assert!(everything.matches_id(&"abc".to_string()));
+
+        let filter = AnchorFilter::new()
+            .with_pathspec(Pathspec::new("a.py", base).unwrap())
+            .with_pathspec(Pathspec::new("b.py", base).unwrap())
+            .with_id_prefix("12");
+        assert!(filter.matches_path(Path::new("/work/a.py")));
+        assert!(filter.matches_path(Path::new("/work/b.py")));
+        assert!(!filter.matches_path(Path::new("/work/c.py")));
+        assert!(filter.matches_id(&"1234".to_string()));
+        assert!(!filter.matches_id(&"2345".to_string()));
+    }
+}
@@ -0,0 +1,195 @@
+extern crate serde_yaml;
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::repository::AnchorId;
+use super::serialization::write_atomically;
+
+/// The location of a single anchor's topic, as recorded in the index.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct IndexEntry {
+    pub id: AnchorId,
+    pub offset: usize,
+    pub width: usize,
+}
+
+/// The modification time and size of an anchor file when it was indexed.
+pub type FileStamp = (SystemTime, u64);
+
+/// The stamp of the file at `path` as it is now.
+pub fn file_stamp(path: &Path) -> io::Result<FileStamp> {
+    let metadata = fs::metadata(path)?;
+    Ok((metadata.modified()?, metadata.len()))
+}
+
+/// Maps anchored files (relative to the repository root) to the anchors in
+/// them.
+///
+/// The index lets per-file queries load only the anchors they need rather
+/// than deserializing every anchor in the repository.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct Index {
+    files: BTreeMap<PathBuf, Vec<IndexEntry>>,
+
+    /// The stamps of all the anchor files the index covers, including any
+    /// that couldn't be read. Comparing these with the anchor files in the
+    /// spor directory shows which have been added, removed or changed behind
+    /// the index's back.
+    #[serde(default)]
+    stamps: BTreeMap<AnchorId, FileStamp>,
+}
+
+impl Index {
+    /// Read the index at `index_path`.
+    ///
+    /// Returns: `None` if there is no index file.
+    pub fn load(index_path: &Path) -> io::Result<Option<Index>> {
+        let f = match File::open(index_path) {
+            Ok(f) => f,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        serde_yaml::from_reader(io::BufReader::new(f))
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, index_path: &Path) -> io::Result<()> {
+        write_atomically(index_path, |writer| {
+            serde_yaml::to_writer(writer, self)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        })
+    }
+
+    /// Record `entry` for `file_path`, replacing any existing entry with the
+    /// same ID.
+    pub fn insert(&mut self, file_path: &Path, entry: IndexEntry) {
+        self.remove_entry(&entry.id);
+        let entries = self.files.entry(file_path.to_path_buf()).or_default();
+        entries.push(entry);
+        entries.sort_by(|a, b| (a.offset, &a.id).cmp(&(b.offset, &b.id)));
+    }
+
+    /// Record the stamp of the anchor file for `anchor_id` as it was when it
+    /// was indexed. Anchor files that can't be read have a stamp but no entry.
+    pub fn set_stamp(&mut self, anchor_id: &AnchorId, stamp: FileStamp) {
+        self.stamps.insert(anchor_id.clone(), stamp);
+    }
+
+    /// The stamp of the anchor file for `anchor_id` when it was indexed.
+    pub fn stamp(&self, anchor_id: &AnchorId) -> Option<&FileStamp> {
+        self.stamps.get(anchor_id)
+    }
+
+    /// Remove the entry and stamp for `anchor_id`.
+    ///
+    /// Returns: Whether the index changed.
+    pub fn remove(&mut self, anchor_id: &AnchorId) -> bool {
+        let covered = self.stamps.remove(anchor_id).is_some();
+        self.remove_entry(anchor_id) || covered
+    }
+
+    fn remove_entry(&mut self, anchor_id: &AnchorId) -> bool {
+        let file_path = self
+            .files
+            .iter()
+            .find(|(_, entries)| entries.iter().any(|e| e.id == *anchor_id))
+            .map(|(file_path, _)| file_path.clone());
+
+        match file_path {
+            None => false,
+            Some(file_path) => {
+                let now_empty = {
+                    let entries = self.files.get_mut(&file_path).unwrap();
+                    entries.retain(|e| e.id != *anchor_id);
+                    entries.is_empty()
+                };
+                if now_empty {
+                    self.files.remove(&file_path);
+                }
+                true
+            }
+        }
+    }
+
+    /// The entries for the anchors in `file_path`, ordered by offset.
+    pub fn entries(&self, file_path: &Path) -> &[IndexEntry] {
+        self.files
+            .get(file_path)
+            .map(|entries| entries.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// All anchored files, relative to the repository root.
+    pub fn files(&self) -> impl Iterator<Item = &PathBuf> {
+        self.files.keys()
+    }
+
+    /// The IDs of all the anchor files the index covers.
+    pub fn anchor_ids(&self) -> impl Iterator<Item = &AnchorId> {
+        self.stamps.keys()
+    }
+
+    /// Whether the index covers exactly the anchor files with `stamps`.
+    pub fn covers(&self, stamps: &BTreeMap<AnchorId, FileStamp>) -> bool {
+        self.stamps == *stamps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, offset: usize) -> IndexEntry {
+        IndexEntry {
+            id: id.to_string(),
+            offset,
+            width: 3,
+        }
+    }
+
+    #[test]
+    fn insert_replaces_existing_entry() {
+        let mut index = Index::default();
+        index.insert(Path::new("a.py"), entry("x", 10));
+        index.insert(Path::new("a.py"), entry("y", 5));
+        index.insert(Path::new("b.py"), entry("x", 1));
+
+        assert_eq!(index.entries(Path::new("a.py")), &[entry("y", 5)]);
+        assert_eq!(index.entries(Path::new("b.py")), &[entry("x", 1)]);
+    }
+
+    #[test]
+    fn remove_drops_empty_files() {
+        let mut index = Index::default();
+        index.insert(Path::new("a.py"), entry("x", 10));
+
+        assert!(index.remove(&"x".to_string()));
+        assert!(!index.remove(&"x".to_string()));
+        assert_eq!(index.files().count(), 0);
+    }
+
+    #[test]
+    fn covers_changed_anchor_files() {
+        let stamp = |len| (SystemTime::UNIX_EPOCH, len);
+        let mut index = Index::default();
+        index.insert(Path::new("a.py"), entry("x", 10));
+        index.set_stamp(&"x".to_string(), stamp(100));
+        index.set_stamp(&"broken".to_string(), stamp(5));
+
+        let mut stamps: BTreeMap<AnchorId, FileStamp> =
+            vec![("x".to_string(), stamp(100)), ("broken".to_string(), stamp(5))].into_iter().collect();
+        assert!(index.covers(&stamps));
+
+        stamps.insert("x".to_string(), stamp(101));
+        assert!(!index.covers(&stamps));
+
+        assert!(index.remove(&"broken".to_string()));
+        assert!(!index.remove(&"broken".to_string()));
+    }
+}
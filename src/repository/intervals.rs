@@ -0,0 +1,109 @@
+use std::cmp::max;
+use std::ops::Range;
+
+/// A static collection of half-open intervals supporting overlap queries.
+///
+/// Intervals are kept sorted by start offset alongside a running maximum of
+/// their end offsets. A query binary-searches for the last interval that
+/// starts before the end of the query range and then walks backwards only as
+/// long as an earlier interval could still reach into the query range.
+pub struct Intervals<T> {
+    entries: Vec<(Range<usize>, T)>,
+    max_ends: Vec<usize>,
+}
+
+impl<T> Intervals<T> {
+    pub fn new(mut entries: Vec<(Range<usize>, T)>) -> Intervals<T> {
+        entries.sort_by_key(|(range, _)| (range.start, range.end));
+
+        let mut max_ends = Vec::with_capacity(entries.len());
+        let mut max_end = 0;
+        for (range, _) in &entries {
+            max_end = max(max_end, interval_end(range));
+            max_ends.push(max_end);
+        }
+
+        Intervals { entries, max_ends }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// All values whose interval contains `position`.
+    pub fn containing(&self, position: usize) -> Vec<&T> {
+        self.overlapping(position..position + 1)
+    }
+
+    /// All values whose interval overlaps `range`, ordered by interval start.
+    ///
+    /// Empty intervals are treated as covering their start offset so that
+    /// zero-width anchors can still be found.
+    pub fn overlapping(&self, range: Range<usize>) -> Vec<&T> {
+        let count = self
+            .entries
+            .binary_search_by(|(r, _)| {
+                if r.start < range.end {
+                    std::cmp::Ordering::Less
+                } else {
+                    std::cmp::Ordering::Greater
+                }
+            })
+            .unwrap_or_else(|index| index);
+
+        let mut matches: Vec<&T> = (0..count)
+            .rev()
+            .take_while(|&index| self.max_ends[index] > range.start)
+            .map(|index| &self.entries[index])
+            .filter(|(r, _)| interval_end(r) > range.start)
+            .map(|(_, value)| value)
+            .collect();
+
+        matches.reverse();
+        matches
+    }
+}
+
+fn interval_end(range: &Range<usize>) -> usize {
+    max(range.end, range.start + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn intervals() -> Intervals<&'static str> {
+        Intervals::new(vec![
+            (10..20, "b"),
+            (0..100, "a"),
+            (15..16, "c"),
+            (30..30, "d"),
+        ])
+    }
+
+    #[test]
+    fn containing_finds_nested_intervals() {
+        let intervals = intervals();
+        assert_eq!(intervals.containing(15), vec![&"a", &"b", &"c"]);
+        assert_eq!(intervals.containing(16), vec![&"a", &"b"]);
+        assert_eq!(intervals.containing(20), vec![&"a"]);
+        assert!(intervals.containing(100).is_empty());
+    }
+
+    #[test]
+    fn empty_intervals_cover_their_start() {
+        let intervals = intervals();
+        assert_eq!(intervals.containing(30), vec![&"a", &"d"]);
+    }
+
+    #[test]
+    fn overlapping_ranges() {
+        let intervals = intervals();
+        assert_eq!(intervals.overlapping(18..40), vec![&"a", &"b", &"d"]);
+        assert_eq!(intervals.overlapping(100..200), Vec::<&&str>::new());
+    }
+}
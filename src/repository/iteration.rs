@@ -1,43 +1,27 @@
+use std::io;
 use std::path::{Path, PathBuf};
 
 use anchor::Anchor;
 use super::repository::{AnchorId, Repository};
-use super::serialization::read_anchor;
+use super::store::{AnchorLoadError, AnchorStore};
 
 impl<'a> IntoIterator for &'a Repository {
     type Item = <RepositoryIterator<'a> as Iterator>::Item;
     type IntoIter = RepositoryIterator<'a>;
 
     fn into_iter(self) -> Self::IntoIter {
-        RepositoryIterator::new(&self.spor_dir(), &self.root)
+        RepositoryIterator::new(self.store())
     }
 }
 
 pub struct RepositoryIterator<'a> {
-    repo_root: &'a Path,
-    anchor_files: Vec<(AnchorId, PathBuf)>
+    anchors: Box<dyn Iterator<Item = (AnchorId, Anchor)> + 'a>,
 }
 
 impl<'a> RepositoryIterator<'a> {
-    fn new(spor_dir: &PathBuf, repo_root: &'a Path) -> RepositoryIterator<'a> {
-        let glob_path = spor_dir.join("**/*.yml");
-
-        let pattern = glob_path
-            .to_str()
-            .expect(format!("Unable to stringify path {:?}. Invalid utf-8?", glob_path).as_str());
-
-        let matches = glob::glob(pattern).expect("Unexpected glob failure.")
-            .filter_map(Result::ok)
-            .map(|anchor_path| anchor_path.file_stem()
-                                .and_then(|id| id.to_str())
-                                .ok_or(())
-                                .map(|id| (id.to_owned(), anchor_path.clone())))
-            .filter_map(Result::ok)
-            .collect();
-
+    fn new(store: &'a dyn AnchorStore) -> RepositoryIterator<'a> {
         RepositoryIterator {
-            repo_root: repo_root,
-            anchor_files: matches,
+            anchors: store.iter(),
         }
     }
 }
@@ -46,13 +30,47 @@ impl<'a> Iterator for RepositoryIterator<'a> {
     type Item = (AnchorId, Anchor);
 
     fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            let (anchor_id, anchor_path) = self.anchor_files.pop()?;
-            match read_anchor(&anchor_path, &self.repo_root) {
-                Ok(anchor) => return Some((anchor_id, anchor)),
-                _ => ()
-            };
-        }
+        self.anchors.next()
     }
 }
 
+/// The directory in the spor directory where broken anchor data is moved.
+pub(crate) const QUARANTINE_DIR: &str = "quarantine";
+
+/// The IDs and paths of all anchor data files in `spor_dir`.
+///
+/// Quarantined files are skipped. Files that were found but whose ID can't be
+/// determined are reported as errors.
+pub(crate) fn anchor_files(spor_dir: &Path) -> Vec<Result<(AnchorId, PathBuf), AnchorLoadError>> {
+    let glob_path = spor_dir.join("**/*.yml");
+    let quarantine_dir = spor_dir.join(QUARANTINE_DIR);
+
+    let pattern = glob_path
+        .to_str()
+        .expect(format!("Unable to stringify path {:?}. Invalid utf-8?", glob_path).as_str());
+
+    glob::glob(pattern).expect("Unexpected glob failure.")
+        .map(|entry| entry.map_err(|err| AnchorLoadError {
+            path: err.path().to_path_buf(),
+            anchor_id: None,
+            cause: io::Error::new(err.error().kind(), err.error().to_string()),
+        }))
+        .filter(|entry| match entry {
+            Ok(anchor_path) => !anchor_path.starts_with(&quarantine_dir),
+            Err(_) => true,
+        })
+        .map(|entry| entry.and_then(|anchor_path| {
+            match anchor_path.file_stem().and_then(|id| id.to_str()) {
+                Some(id) => Ok((id.to_owned(), anchor_path.clone())),
+                None => Err(AnchorLoadError {
+                    path: anchor_path.clone(),
+                    anchor_id: None,
+                    cause: io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Anchor file name is not valid UTF-8",
+                    ),
+                }),
+            }
+        }))
+        .collect()
+}
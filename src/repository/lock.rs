@@ -0,0 +1,83 @@
+extern crate fs2;
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use self::fs2::FileExt;
+
+/// How long mutations wait for another spor process to release the lock.
+pub(crate) const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+const RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// An exclusive advisory lock on a repository.
+///
+/// Every mutation of the repository happens while holding this lock so that
+/// concurrent spor processes (e.g. an editor plugin and a git hook) take
+/// turns rather than interleaving their writes. The lock is released when
+/// this is dropped, or by the OS if the process dies.
+#[derive(Debug)]
+pub(crate) struct RepositoryLock {
+    file: File,
+}
+
+impl RepositoryLock {
+    /// Lock the repository whose data lives in `spor_dir`, waiting up to
+    /// `timeout` for another process to release it.
+    pub fn acquire(spor_dir: &Path, timeout: Duration) -> io::Result<RepositoryLock> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(spor_dir.join("lock"))?;
+
+        let start = Instant::now();
+        loop {
+            match file.try_lock_exclusive() {
+                Ok(()) => return Ok(RepositoryLock { file }),
+                Err(ref err) if err.kind() == fs2::lock_contended_error().kind() => {
+                    if start.elapsed() >= timeout {
+                        return Err(io::Error::new(
+                            io::ErrorKind::WouldBlock,
+                            format!(
+                                "Repository {:?} is locked by another spor process",
+                                spor_dir
+                            ),
+                        ));
+                    }
+                    sleep(RETRY_INTERVAL);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl Drop for RepositoryLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+
+    #[test]
+    fn second_lock_times_out() {
+        let dir = tempdir::TempDir::new("spor_lock_tests").unwrap();
+
+        let lock = RepositoryLock::acquire(dir.path(), LOCK_TIMEOUT).unwrap();
+        let err = RepositoryLock::acquire(dir.path(), Duration::from_millis(100)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        drop(lock);
+        RepositoryLock::acquire(dir.path(), Duration::from_millis(100)).unwrap();
+    }
+}
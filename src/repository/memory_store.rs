@@ -0,0 +1,73 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::Mutex;
+
+use anchor::Anchor;
+use super::repository::AnchorId;
+use super::store::{AnchorLoadError, AnchorStore};
+
+/// Keeps anchors in memory only.
+///
+/// This is useful for tests and for embedding spor in tools that manage
+/// persistence themselves.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    anchors: Mutex<BTreeMap<AnchorId, Anchor>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+impl AnchorStore for MemoryStore {
+    fn get(&self, anchor_id: &AnchorId) -> io::Result<Option<Anchor>> {
+        Ok(self.anchors.lock().unwrap().get(anchor_id).cloned())
+    }
+
+    fn put(&self, anchor_id: &AnchorId, anchor: &Anchor) -> io::Result<()> {
+        self.anchors
+            .lock()
+            .unwrap()
+            .insert(anchor_id.clone(), anchor.clone());
+        Ok(())
+    }
+
+    fn update(&self, anchor_id: &AnchorId, anchor: &Anchor) -> io::Result<bool> {
+        match self.anchors.lock().unwrap().get_mut(anchor_id) {
+            Some(existing) => {
+                *existing = anchor.clone();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn delete(&self, anchor_id: &AnchorId) -> io::Result<Option<Anchor>> {
+        Ok(self.anchors.lock().unwrap().remove(anchor_id))
+    }
+
+    fn try_iter<'a>(&'a self) -> Box<dyn Iterator<Item = Result<(AnchorId, Anchor), AnchorLoadError>> + 'a> {
+        let anchors: Vec<Result<(AnchorId, Anchor), AnchorLoadError>> = self
+            .anchors
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, anchor)| Ok((id.clone(), anchor.clone())))
+            .collect();
+        Box::new(anchors.into_iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::store::conformance::check_store;
+    use std::path::Path;
+
+    #[test]
+    fn conformance() {
+        check_store(&MemoryStore::new(), Path::new("/repo"));
+    }
+}
@@ -1,5 +1,21 @@
+mod config;
+mod dir_store;
+mod filter;
+mod index;
+mod intervals;
 mod iteration;
+mod lock;
+mod memory_store;
 mod repository;
 mod serialization;
+mod single_file_store;
+mod store;
 
-pub use self::repository::{AnchorId, initialize, Repository};
\ No newline at end of file
+pub use self::config::StoreKind;
+pub use self::dir_store::DirStore;
+pub use self::filter::{AnchorFilter, Pathspec};
+pub use self::intervals::Intervals;
+pub use self::memory_store::MemoryStore;
+pub use self::repository::{AnchorId, initialize, initialize_with_store, Repository};
+pub use self::single_file_store::SingleFileStore;
+pub use self::store::{AnchorLoadError, AnchorStore};
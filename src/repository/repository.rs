@@ -1,5 +1,6 @@
 extern crate glob;
 extern crate serde;
+extern crate serde_yaml;
 extern crate uuid;
 
 use std::fs::DirBuilder;
@@ -7,7 +8,14 @@ use std::io;
 use std::path::{Path, PathBuf};
 
 use anchor::Anchor;
-use super::serialization::{read_anchor, write_anchor};
+use super::config::{Config, StoreKind};
+use super::dir_store::DirStore;
+use super::filter::AnchorFilter;
+use super::intervals::Intervals;
+use super::memory_store::MemoryStore;
+use super::serialization::FORMAT_VERSION;
+use super::single_file_store::SingleFileStore;
+use super::store::{AnchorLoadError, AnchorStore};
 
 pub type AnchorId = String;
 
@@ -19,47 +27,111 @@ fn new_anchor_id() -> AnchorId {
 pub struct Repository {
     pub root: PathBuf,
     spor_dir: PathBuf,
+    config: Config,
+    store: Box<dyn AnchorStore>,
 }
 
 impl Repository {
     /// Find the repository directory for the file `path` and return a
     /// `Repository` for it.
+    ///
+    /// The anchor store is chosen based on the repository's config.
     pub fn new(path: &Path, spor_dir: Option<&Path>) -> io::Result<Repository> {
         let spor_dir = PathBuf::from(spor_dir.unwrap_or(&PathBuf::from(".spor")));
 
-        find_root_dir(path, &spor_dir)
-            .map(|root_dir| {
-                assert!(
-                    root_dir.join(&spor_dir).exists(),
-                    "spor-dir not found after find_root_dir succeeded!"
-                );
+        let root_dir = find_root_dir(path, &spor_dir)?;
+        assert!(
+            root_dir.join(&spor_dir).exists(),
+            "spor-dir not found after find_root_dir succeeded!"
+        );
 
-                Repository {
-                    root: root_dir,
-                    spor_dir: spor_dir,
-                }
-            })
+        let full_spor_dir = root_dir.join(&spor_dir);
+        let config = Config::load(&full_spor_dir)?;
+        if config.format_version > FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Repository format version {} is newer than this spor supports ({})",
+                    config.format_version, FORMAT_VERSION
+                ),
+            ));
+        }
+
+        let store: Box<dyn AnchorStore> = match config.store {
+            StoreKind::Directory => Box::new(DirStore::new(&root_dir, &full_spor_dir)),
+            StoreKind::SingleFile => Box::new(SingleFileStore::new(&root_dir, &full_spor_dir)),
+        };
+
+        Ok(Repository {
+            root: root_dir,
+            spor_dir,
+            config,
+            store,
+        })
+    }
+
+    /// Create a repository for `root` that keeps its anchors in `store`.
+    ///
+    /// Nothing is read from or written to a spor directory other than what
+    /// `store` itself does.
+    pub fn with_store(root: &Path, store: Box<dyn AnchorStore>) -> Repository {
+        Repository {
+            root: root.to_path_buf(),
+            spor_dir: PathBuf::from(".spor"),
+            config: Config::default(),
+            store,
+        }
+    }
+
+    /// Create a repository for `root` that keeps its anchors in memory.
+    pub fn in_memory(root: &Path) -> Repository {
+        Repository::with_store(root, Box::new(MemoryStore::new()))
+    }
+
+    /// The anchor format version of the repository.
+    ///
+    /// Anchors in older formats are upgraded as they're read, but they're
+    /// only rewritten on disk by `migrate`.
+    pub fn format_version(&self) -> u64 {
+        self.config.format_version
+    }
+
+    /// The name of the alignment algorithm configured for the repository,
+    /// if any.
+    pub fn algorithm(&self) -> Option<&str> {
+        self.config.algorithm.as_deref()
     }
 
     pub fn spor_dir(&self) -> PathBuf {
         self.root.join(&self.spor_dir)
     }
 
+    /// The store holding the repository's anchors.
+    pub fn store(&self) -> &dyn AnchorStore {
+        self.store.as_ref()
+    }
+
     pub fn add(
         &self,
         anchor: Anchor,
     ) -> io::Result<AnchorId> {
+        if anchor.file_path().strip_prefix(&self.root).is_err() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Anchored file not within repository",
+            ));
+        }
+
         let anchor_id = new_anchor_id();
-        let anchor_path = self.anchor_path(&anchor_id);
 
-        if anchor_path.exists() {
+        if self.store.get(&anchor_id)?.is_some() {
             return Err(io::Error::new(
                 io::ErrorKind::AlreadyExists,
-                format!("{:?} already exists", anchor_path),
+                format!("{} already exists", anchor_id),
             ));
         }
 
-        write_anchor(&anchor_path, &anchor, &self.root)?;
+        self.store.put(&anchor_id, &anchor)?;
 
         Ok(anchor_id)
     }
@@ -69,53 +141,191 @@ impl Repository {
         anchor_id: AnchorId,
         anchor: &Anchor
     ) -> io::Result<()> {
-        let anchor_path = self.anchor_path(&anchor_id);
-        if !anchor_path.exists() {
+        if !self.store.update(&anchor_id, anchor)? {
             return Err(io::Error::new(
                 io::ErrorKind::NotFound,
-                format!("{:?} does not exist", anchor_path)
+                format!("{} does not exist", anchor_id)
             ));
         }
 
-        write_anchor(&anchor_path, &anchor, &self.root)?;
-
         Ok(())
     }
 
-    /// Absolute path to the data file for `anchor_id`.
-    fn anchor_path(&self, anchor_id: &AnchorId) -> PathBuf {
-        let file_name = format!("{}.yml", anchor_id);
-        let path = self.spor_dir().join(file_name);
-        assert!(path.is_absolute());
-        path
+    /// Change the metadata of the anchor `anchor_id`.
+    ///
+    /// `modify` edits the current metadata in place, and the result is saved
+    /// with `update`.
+    ///
+    /// Returns: The updated anchor.
+    pub fn update_metadata<F>(&self, anchor_id: &AnchorId, modify: F) -> io::Result<Anchor>
+    where
+        F: FnOnce(&mut serde_yaml::Value) -> io::Result<()>,
+    {
+        let anchor = self.get(anchor_id)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} does not exist", anchor_id),
+            )
+        })?;
+
+        let mut metadata = anchor.metadata().clone();
+        modify(&mut metadata)?;
+
+        let anchor = Anchor::new(
+            anchor.file_path(),
+            anchor.context().clone(),
+            metadata,
+            anchor.encoding().clone(),
+        )?
+        .with_scoring(anchor.scoring().map(str::to_owned));
+
+        self.update(anchor_id.clone(), &anchor)?;
+        Ok(anchor)
     }
 
     pub fn get(&self, anchor_id: &AnchorId) -> io::Result<Option<Anchor>> {
-        let path = self.anchor_path(anchor_id);
-        match read_anchor(&path, &self.root) {
-            Err(err) => {
-                match err.kind() {
-                    io::ErrorKind::NotFound => {
-                        Ok(None)
-                    }        
-                    _ => Err(err)
-                }
+        self.store.get(anchor_id)
+    }
+
+    /// Remove the anchor `anchor_id` from the repository.
+    ///
+    /// Returns: The anchor that was removed.
+    pub fn remove(&self, anchor_id: &AnchorId) -> io::Result<Anchor> {
+        self.store.delete(anchor_id)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} does not exist", anchor_id),
+            )
+        })
+    }
+
+    /// All anchors in the repository, including those that couldn't be
+    /// loaded.
+    ///
+    /// Iterating over the repository itself silently skips broken anchors.
+    pub fn try_iter<'a>(&'a self) -> Box<dyn Iterator<Item = Result<(AnchorId, Anchor), AnchorLoadError>> + 'a> {
+        self.store.try_iter()
+    }
+
+    /// Move the data behind a broken anchor into the quarantine directory.
+    ///
+    /// Returns: Where the data was moved to.
+    pub fn quarantine(&self, error: &AnchorLoadError) -> io::Result<PathBuf> {
+        self.store.quarantine(error)
+    }
+
+    /// Rebuild the anchor index from the anchor data.
+    ///
+    /// Anchor files added, removed or rewritten other than through spor are
+    /// noticed without this, so it's only needed if the index is damaged.
+    pub fn reindex(&self) -> io::Result<()> {
+        self.store.reindex()
+    }
+
+    /// Rewrite every anchor in the repository in the current format.
+    ///
+    /// Returns: The number of anchors that were upgraded.
+    pub fn migrate(&mut self) -> io::Result<usize> {
+        let migrated = self.store.migrate()?;
+
+        if self.config.format_version < FORMAT_VERSION {
+            self.config.format_version = FORMAT_VERSION;
+            self.config.save(&self.spor_dir())?;
+        }
+
+        Ok(migrated)
+    }
+
+    /// All anchored files in the repository.
+    pub fn files(&self) -> io::Result<Vec<PathBuf>> {
+        self.store.files()
+    }
+
+    /// All anchors in `path`, ordered by offset.
+    pub fn anchors_in(&self, path: &Path) -> io::Result<Vec<(AnchorId, Anchor)>> {
+        let locations = self.store.locations(&self.absolute_path(path)?)?;
+        self.load_entries(locations.iter().map(|(id, _)| id))
+    }
+
+    /// All anchors selected by `filter`, grouped by file and ordered by
+    /// offset within each file.
+    ///
+    /// Files and IDs are checked against the index, so only the selected
+    /// anchors are loaded.
+    pub fn anchors_matching(&self, filter: &AnchorFilter) -> io::Result<Vec<(AnchorId, Anchor)>> {
+        let mut anchors = Vec::new();
+        for file in self.files()? {
+            if !filter.matches_path(&file) {
+                continue;
             }
-            Ok(anchor) => Ok(Some(anchor))
+
+            let locations = self.store.locations(&file)?;
+            anchors.extend(self.load_entries(
+                locations
+                    .iter()
+                    .map(|(id, _)| id)
+                    .filter(|id| filter.matches_id(id)),
+            )?);
         }
+        Ok(anchors)
     }
 
-    // get by id
-    // update
-    // remove
-    // iterate
-    // items
-}
+    /// Build an interval index over the topics of all anchors in `path`.
+    ///
+    /// The intervals are built without loading any anchors where the store
+    /// allows it. The result can be queried repeatedly, e.g. as the cursor
+    /// moves around a file in an editor.
+    pub fn file_intervals(&self, path: &Path) -> io::Result<Intervals<AnchorId>> {
+        let locations = self.store.locations(&self.absolute_path(path)?)?;
+
+        let entries = locations
+            .into_iter()
+            .map(|(id, range)| (range, id))
+            .collect();
+
+        Ok(Intervals::new(entries))
+    }
 
+    /// Find all anchors in `path` whose topic covers the character `offset`.
+    pub fn anchors_at(&self, path: &Path, offset: usize) -> io::Result<Vec<(AnchorId, Anchor)>> {
+        let intervals = self.file_intervals(path)?;
+        self.load_entries(intervals.containing(offset).into_iter())
+    }
 
+    /// Load the anchors for `anchor_ids`, skipping any that no longer exist.
+    fn load_entries<'a, I>(&self, anchor_ids: I) -> io::Result<Vec<(AnchorId, Anchor)>>
+    where
+        I: Iterator<Item = &'a AnchorId>,
+    {
+        let mut anchors = Vec::new();
+        for anchor_id in anchor_ids {
+            if let Some(anchor) = self.get(anchor_id)? {
+                anchors.push((anchor_id.clone(), anchor));
+            }
+        }
+        Ok(anchors)
+    }
+
+    /// The absolute form of `path`.
+    ///
+    /// Relative paths are taken to be relative to the current directory.
+    fn absolute_path(&self, path: &Path) -> io::Result<PathBuf> {
+        if path.is_absolute() {
+            Ok(path.to_path_buf())
+        } else {
+            path.canonicalize()
+        }
+    }
+}
 
 /// Initialize a spor repository in `path` if one doesn't already exist.
 pub fn initialize(path: &Path, spor_dir: Option<&Path>) -> io::Result<()> {
+    initialize_with_store(path, spor_dir, StoreKind::default())
+}
+
+/// Initialize a spor repository in `path` that stores its anchors in the way
+/// described by `store`.
+pub fn initialize_with_store(path: &Path, spor_dir: Option<&Path>, store: StoreKind) -> io::Result<()> {
     let spor_dir = spor_dir.unwrap_or(Path::new(".spor"));
 
     let spor_path = path.join(spor_dir);
@@ -126,7 +336,13 @@ pub fn initialize(path: &Path, spor_dir: Option<&Path>) -> io::Result<()> {
     } else {
         let mut builder = DirBuilder::new();
         builder.recursive(true);
-        builder.create(spor_path)
+        builder.create(&spor_path)?;
+
+        let config = Config {
+            store,
+            ..Config::default()
+        };
+        config.save(&spor_path)
     }
 }
 
@@ -148,3 +364,247 @@ fn find_root_dir(path: &Path, spor_dir: &Path) -> io::Result<PathBuf> {
                 .next()
         }).map(|a| PathBuf::from(a.unwrap()))
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use super::super::filter::Pathspec;
+    use super::super::serialization::{format_version, read_mapping, write_anchor};
+    use alignment::linear_space;
+    use anchor::Context;
+    use status::{classify, Status};
+    use std::fs;
+    use updating::UpdateOptions;
+
+    fn make_repo() -> (tempdir::TempDir, Repository) {
+        let dir = tempdir::TempDir::new("spor_repository_tests").unwrap();
+        initialize(dir.path(), None).unwrap();
+        let repo = Repository::new(dir.path(), None).unwrap();
+        (dir, repo)
+    }
+
+    fn make_anchor(repo: &Repository) -> Anchor {
+        let source_path = repo.root.join("source.py");
+        fs::write(&source_path, "def func():\n    return 42\n").unwrap();
+        let context = Context::new("def func():\n    return 42\n", 4, 4, 3).unwrap();
+        Anchor::new(
+            &source_path,
+            context,
+            serde_yaml::from_str("foo: bar").unwrap(),
+            "utf-8".to_string(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn remove_returns_removed_anchor() {
+        let (_dir, repo) = make_repo();
+        let anchor = make_anchor(&repo);
+        let anchor_id = repo.add(anchor).unwrap();
+
+        let removed = repo.remove(&anchor_id).unwrap();
+        assert_eq!(removed.context().topic(), "func");
+        assert!(repo.get(&anchor_id).unwrap().is_none());
+    }
+
+    #[test]
+    fn update_metadata_saves_changes() {
+        let (_dir, repo) = make_repo();
+        let anchor_id = repo.add(make_anchor(&repo)).unwrap();
+
+        repo.update_metadata(&anchor_id, |metadata| {
+            *metadata = serde_yaml::from_str("{reviewed: true}").unwrap();
+            Ok(())
+        })
+        .unwrap();
+
+        let anchor = repo.get(&anchor_id).unwrap().unwrap();
+        assert_eq!(anchor.metadata()["reviewed"], serde_yaml::Value::Bool(true));
+        assert_eq!(anchor.context().topic(), "func");
+
+        let err = repo
+            .update_metadata(&"no-such-anchor".to_string(), |_| Ok(()))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn anchors_at_finds_covering_anchors() {
+        let (_dir, repo) = make_repo();
+        let anchor = make_anchor(&repo);
+        let source_path = anchor.file_path().clone();
+        let anchor_id = repo.add(anchor).unwrap();
+
+        let found = repo.anchors_at(&source_path, 5).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, anchor_id);
+
+        assert!(repo.anchors_at(&source_path, 8).unwrap().is_empty());
+    }
+
+    #[test]
+    fn index_follows_mutations() {
+        let (_dir, repo) = make_repo();
+        let anchor = make_anchor(&repo);
+        let source_path = anchor.file_path().clone();
+        let anchor_id = repo.add(anchor).unwrap();
+
+        assert_eq!(repo.files().unwrap(), vec![source_path.clone()]);
+        assert_eq!(repo.anchors_in(&source_path).unwrap().len(), 1);
+
+        repo.remove(&anchor_id).unwrap();
+        assert!(repo.files().unwrap().is_empty());
+        assert!(repo.anchors_in(&source_path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn anchors_matching_filters_files_and_ids() {
+        let (_dir, repo) = make_repo();
+        let anchor = make_anchor(&repo);
+        let source_path = anchor.file_path().clone();
+        let first = repo.add(anchor.clone()).unwrap();
+        let second = repo.add(anchor).unwrap();
+
+        let other_path = repo.root.join("other.py");
+        fs::write(&other_path, "def other():").unwrap();
+        let context = Context::new("def other():", 4, 5, 3).unwrap();
+        let other = Anchor::new(&other_path, context, serde_yaml::Value::Null, "utf-8".to_string()).unwrap();
+        repo.add(other).unwrap();
+
+        assert_eq!(repo.anchors_matching(&AnchorFilter::new()).unwrap().len(), 3);
+
+        let root = repo.root.canonicalize().unwrap();
+        let by_file = AnchorFilter::new().with_pathspec(Pathspec::new("source.py", &root).unwrap());
+        let found: Vec<AnchorId> = repo
+            .anchors_matching(&by_file)
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(found.len(), 2);
+        assert!(found.contains(&first) && found.contains(&second));
+
+        let by_id = by_file.with_id_prefix(&second[..8]);
+        let found = repo.anchors_matching(&by_id).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, second);
+        assert_eq!(found[0].1.file_path(), &source_path);
+    }
+
+    #[test]
+    fn store_is_chosen_from_config() {
+        let dir = tempdir::TempDir::new("spor_repository_tests").unwrap();
+        initialize_with_store(dir.path(), None, StoreKind::SingleFile).unwrap();
+        let repo = Repository::new(dir.path(), None).unwrap();
+
+        let anchor_id = repo.add(make_anchor(&repo)).unwrap();
+        assert!(dir.path().join(".spor").join("anchors.yaml").exists());
+        assert!(repo.get(&anchor_id).unwrap().is_some());
+    }
+
+    #[test]
+    fn in_memory_repository() {
+        let repo = Repository::in_memory(Path::new("/repo"));
+        let context = Context::new("def func():", 4, 4, 3).unwrap();
+        let anchor = Anchor::new(
+            Path::new("/repo/source.py"),
+            context,
+            serde_yaml::Value::Null,
+            "utf-8".to_string(),
+        )
+        .unwrap();
+
+        let anchor_id = repo.add(anchor).unwrap();
+        assert_eq!(repo.files().unwrap(), vec![PathBuf::from("/repo/source.py")]);
+        assert_eq!(repo.anchors_at(Path::new("/repo/source.py"), 4).unwrap()[0].0, anchor_id);
+    }
+
+    #[test]
+    fn migrate_upgrades_legacy_repository() {
+        let (dir, _repo) = make_repo();
+        let spor_dir = dir.path().join(".spor");
+        fs::remove_file(spor_dir.join("config")).unwrap();
+        fs::write(dir.path().join("source.py"), "def func():").unwrap();
+        fs::write(
+            spor_dir.join("legacy.yml"),
+            "file_path: source.py
+encoding: utf-8
+metadata: {}
+context: {before: 'def ', offset: 4, topic: func, after: '():', width: 3}
+",
+        )
+        .unwrap();
+
+        let mut repo = Repository::new(dir.path(), None).unwrap();
+        assert_eq!(repo.format_version(), 1);
+        assert!(repo.get(&"legacy".to_string()).unwrap().is_some());
+
+        assert_eq!(repo.migrate().unwrap(), 1);
+        assert_eq!(repo.migrate().unwrap(), 0);
+
+        let repo = Repository::new(dir.path(), None).unwrap();
+        assert_eq!(repo.format_version(), FORMAT_VERSION);
+        let m = read_mapping(&spor_dir.join("legacy.yml")).unwrap();
+        assert_eq!(format_version(&m).unwrap(), FORMAT_VERSION);
+    }
+
+    #[test]
+    fn legacy_long_after_context_is_intact() {
+        let (dir, repo) = make_repo();
+        let spor_dir = dir.path().join(".spor");
+        fs::write(dir.path().join("source.py"), "def func():\n    return 42\n").unwrap();
+
+        // Older versions of spor kept `offset + topic length + width`
+        // characters after the topic.
+        fs::write(
+            spor_dir.join("legacy.yml"),
+            "file_path: source.py
+encoding: utf-8
+metadata: {}
+context: {before: 'def ', offset: 4, topic: func, after: \"():\\n    retur\", width: 4}
+",
+        )
+        .unwrap();
+
+        let anchor = repo.get(&"legacy".to_string()).unwrap().unwrap();
+        let status = classify(&anchor, &linear_space::align, &UpdateOptions::default()).unwrap();
+        assert_eq!(status, Status::Intact);
+    }
+
+    #[test]
+    fn anchor_files_rewritten_in_place_are_reindexed() {
+        let (dir, repo) = make_repo();
+        let anchor_id = repo.add(make_anchor(&repo)).unwrap();
+        let source_path = repo.root.join("source.py");
+        assert_eq!(repo.anchors_at(&source_path, 5).unwrap().len(), 1);
+
+        // E.g. a teammate's update, brought in by a pull, moved the anchor
+        // to another file.
+        let other_path = repo.root.join("other.py");
+        let text = "# moved\ndef func():\n    return 42\n";
+        fs::write(&other_path, text).unwrap();
+        let moved = Anchor::new(
+            &other_path,
+            Context::new(text, 12, 4, 3).unwrap(),
+            serde_yaml::Value::Null,
+            "utf-8".to_string(),
+        )
+        .unwrap();
+        let anchor_path = dir.path().join(".spor").join(format!("{}.yml", anchor_id));
+        write_anchor(&anchor_path, &moved, &repo.root).unwrap();
+
+        assert!(repo.anchors_in(&source_path).unwrap().is_empty());
+        assert!(repo.anchors_at(&source_path, 5).unwrap().is_empty());
+        assert_eq!(repo.anchors_in(&other_path).unwrap()[0].0, anchor_id);
+        assert_eq!(repo.anchors_at(&other_path, 13).unwrap()[0].1.context().offset(), 12);
+    }
+
+    #[test]
+    fn remove_missing_anchor_fails() {
+        let (_dir, repo) = make_repo();
+        let err = repo.remove(&"no-such-anchor".to_string()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -1,19 +1,92 @@
 extern crate serde_yaml;
+extern crate uuid;
 
-use std::fs::File;
-use std::io;
+use std::fs::{remove_file, rename, File};
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 
 use anchor::{Anchor, Context};
+use position::OffsetUnit;
+
+/// Write a file by way of a temporary file in the same directory.
+///
+/// `write` fills in the temporary file, which is then renamed over `path`.
+/// Readers therefore see either the old or the new contents, never a partial
+/// write, even if spor crashes part way through.
+pub(crate) fn write_atomically<F>(path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut io::BufWriter<&File>) -> io::Result<()>,
+{
+    let dir = path.parent().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("{:?} has no parent", path))
+    })?;
+
+    let file_name = path.file_name().and_then(|n| n.to_str()).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid file name: {:?}", path))
+    })?;
+
+    // The ".tmp" suffix keeps partially written files out of anchor globs.
+    let temp_path = dir.join(format!(".{}.{}.tmp", file_name, uuid::Uuid::new_v4()));
+
+    let result = File::create(&temp_path).and_then(|f| {
+        {
+            let mut writer = io::BufWriter::new(&f);
+            write(&mut writer)?;
+            writer.flush()?;
+        }
+        f.sync_all()?;
+        rename(&temp_path, path)
+    });
+
+    if result.is_err() {
+        let _ = remove_file(&temp_path);
+    }
+
+    result
+}
+
+/// The version of the on-disk anchor format written by this version of spor.
+///
+/// Anchor files written before the format was versioned are version 1.
+pub(crate) const FORMAT_VERSION: u64 = 4;
+
+/// The unit of the offsets and widths in anchor files, as recorded in their
+/// `offset_unit` field.
+pub(crate) const OFFSET_UNIT: OffsetUnit = OffsetUnit::Char;
+
+/// Functions that upgrade an anchor mapping by one format version.
+///
+/// `MIGRATIONS[n]` converts a mapping from version `n + 1` to version `n + 2`.
+const MIGRATIONS: [fn(serde_yaml::Mapping) -> io::Result<serde_yaml::Mapping>; 3] =
+    [migrate_v1_to_v2, migrate_v2_to_v3, migrate_v3_to_v4];
 
 pub(crate) fn write_anchor(
     anchor_path: &Path,
     anchor: &Anchor,
     repo_root: &Path,
 ) -> io::Result<()> {
-    let f = File::create(anchor_path)?;
-    let writer = io::BufWriter::new(f);
+    let m = anchor_to_mapping(anchor, repo_root)?;
+
+    write_atomically(anchor_path, |writer| {
+        serde_yaml::to_writer(writer, &m)
+            .map_err(|info| io::Error::new(io::ErrorKind::InvalidData, info))
+    })
+}
 
+pub(crate) fn read_anchor(anchor_path: &Path, repo_root: &Path) -> io::Result<Anchor> {
+    anchor_from_mapping(read_mapping(anchor_path)?, repo_root)
+}
+
+/// Read the raw, unmigrated contents of an anchor file.
+pub(crate) fn read_mapping(anchor_path: &Path) -> io::Result<serde_yaml::Mapping> {
+    let f = File::open(anchor_path)?;
+    let reader = io::BufReader::new(f);
+    serde_yaml::from_reader(reader)
+        .map_err(|info| io::Error::new(io::ErrorKind::InvalidData, info))
+}
+
+/// Serialize `anchor` into the current anchor format.
+pub(crate) fn anchor_to_mapping(anchor: &Anchor, repo_root: &Path) -> io::Result<serde_yaml::Mapping> {
     let mut m = serde_yaml::Mapping::new();
     let file_path = anchor.file_path().strip_prefix(repo_root).map_err(|_| {
         io::Error::new(
@@ -27,6 +100,16 @@ pub(crate) fn write_anchor(
         "unable to serialize file path",
     ))?;
 
+    m.insert(
+        serde_yaml::Value::String("format_version".to_owned()),
+        serde_yaml::Value::Number(FORMAT_VERSION.into()),
+    );
+
+    m.insert(
+        serde_yaml::Value::String("offset_unit".to_owned()),
+        serde_yaml::Value::String(OFFSET_UNIT.name().to_owned()),
+    );
+
     m.insert(
         serde_yaml::Value::String("file_path".to_owned()),
         serde_yaml::Value::String(file_path.to_owned()),
@@ -50,67 +133,285 @@ pub(crate) fn write_anchor(
         )))?,
     );
 
-    match serde_yaml::to_writer(writer, &m) {
-        Err(info) => Err(io::Error::new(io::ErrorKind::InvalidData, info)),
-        Ok(s) => Ok(s),
+    if let Some(scoring) = anchor.scoring() {
+        m.insert(
+            serde_yaml::Value::String("scoring".to_owned()),
+            serde_yaml::Value::String(scoring.to_owned()),
+        );
     }
+
+    Ok(m)
 }
 
-pub(crate) fn read_anchor(anchor_path: &Path, repo_root: &Path) -> io::Result<Anchor> {
-    let f = File::open(anchor_path)?;
-    let reader = io::BufReader::new(f);
-    match serde_yaml::from_reader(reader) {
-        Err(info) => return Err(io::Error::new(io::ErrorKind::InvalidData, info)),
-        Ok(m) => {
-            let m: serde_yaml::Mapping = m;
-
-            let fp = m
-                .get(&serde_yaml::Value::String("file_path".to_owned()))
-                .and_then(|value| value.as_str())
-                .map(|value| {
-                    let mut path = PathBuf::new();
-                    path.push(value);
-                    path
-                })
-                .ok_or(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    "Expected file_path field",
-                ))?;
-
-            let encoding = m
-                .get(&serde_yaml::Value::String("encoding".to_owned()))
-                .and_then(|v| v.as_str())
-                .ok_or(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    "expected encoding field",
-                ))?;
-
-            let metadata = m
-                .get(&serde_yaml::Value::String("metadata".to_owned()))
-                .ok_or(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    "expected metadata field",
-                ))?;
-
-            let context = m
-                .get(&serde_yaml::Value::String("context".to_owned()))
-                .ok_or(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    "expected context field",
-                ))?;
-
-            let context: Context = serde_yaml::from_value(context.clone()).map_err(|_e| {
-                io::Error::new(io::ErrorKind::InvalidInput, "expected context field")
-            })?;
-
-            let a = Anchor::new(
-                &repo_root.join(fp),
-                context,
-                metadata.clone(),
-                encoding.to_owned(),
-            )?;
-
-            Ok(a)
+/// Deserialize an anchor from a mapping in any supported anchor format.
+pub(crate) fn anchor_from_mapping(m: serde_yaml::Mapping, repo_root: &Path) -> io::Result<Anchor> {
+    let m = migrate(m)?;
+
+    let offset_unit = m
+        .get(&serde_yaml::Value::String("offset_unit".to_owned()))
+        .and_then(|value| value.as_str())
+        .ok_or(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Expected offset_unit field",
+        ))?;
+    if offset_unit != OFFSET_UNIT.name() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unsupported offset unit: {}", offset_unit),
+        ));
+    }
+
+    let fp = m
+        .get(&serde_yaml::Value::String("file_path".to_owned()))
+        .and_then(|value| value.as_str())
+        .map(|value| {
+            let mut path = PathBuf::new();
+            path.push(value);
+            path
+        })
+        .ok_or(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Expected file_path field",
+        ))?;
+
+    let encoding = m
+        .get(&serde_yaml::Value::String("encoding".to_owned()))
+        .and_then(|v| v.as_str())
+        .ok_or(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "expected encoding field",
+        ))?;
+
+    let metadata = m
+        .get(&serde_yaml::Value::String("metadata".to_owned()))
+        .ok_or(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "expected metadata field",
+        ))?;
+
+    let context = m
+        .get(&serde_yaml::Value::String("context".to_owned()))
+        .ok_or(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "expected context field",
+        ))?;
+
+    let context: Context = serde_yaml::from_value(context.clone()).map_err(|_e| {
+        io::Error::new(io::ErrorKind::InvalidInput, "expected context field")
+    })?;
+
+    let scoring = match m.get(&serde_yaml::Value::String("scoring".to_owned())) {
+        None => None,
+        Some(scoring) => Some(scoring.as_str().map(str::to_owned).ok_or(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "scoring must be the name of a scoring profile",
+        ))?),
+    };
+
+    let a = Anchor::new(
+        &repo_root.join(fp),
+        context,
+        metadata.clone(),
+        encoding.to_owned(),
+    )?
+    .with_scoring(scoring);
+
+    Ok(a)
+}
+
+/// The format version of an anchor mapping.
+pub(crate) fn format_version(m: &serde_yaml::Mapping) -> io::Result<u64> {
+    match m.get(&serde_yaml::Value::String("format_version".to_owned())) {
+        None => Ok(1),
+        Some(version) => version.as_u64().ok_or(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "format_version must be a positive integer",
+        )),
+    }
+}
+
+/// Upgrade an anchor mapping to the current format version.
+fn migrate(mut m: serde_yaml::Mapping) -> io::Result<serde_yaml::Mapping> {
+    let version = format_version(&m)?;
+
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Unsupported anchor format version {} (this spor supports up to {})",
+                version, FORMAT_VERSION
+            ),
+        ));
+    }
+
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        m = migration(m)?;
+    }
+
+    Ok(m)
+}
+
+/// Version 2 only adds the `format_version` field.
+fn migrate_v1_to_v2(mut m: serde_yaml::Mapping) -> io::Result<serde_yaml::Mapping> {
+    m.insert(
+        serde_yaml::Value::String("format_version".to_owned()),
+        serde_yaml::Value::Number(2.into()),
+    );
+    Ok(m)
+}
+
+/// Version 3 records the unit of offsets and widths. Earlier versions always
+/// counted characters, even where some checks counted bytes.
+fn migrate_v2_to_v3(mut m: serde_yaml::Mapping) -> io::Result<serde_yaml::Mapping> {
+    m.insert(
+        serde_yaml::Value::String("format_version".to_owned()),
+        serde_yaml::Value::Number(3.into()),
+    );
+    m.insert(
+        serde_yaml::Value::String("offset_unit".to_owned()),
+        serde_yaml::Value::String(OffsetUnit::Char.name().to_owned()),
+    );
+    Ok(m)
+}
+
+/// Version 4 limits the context after the topic to `width` characters.
+///
+/// Earlier versions stored up to `offset + topic length + width` characters
+/// after the topic, so anchors in unchanged files looked changed once their
+/// context was recomputed.
+fn migrate_v3_to_v4(mut m: serde_yaml::Mapping) -> io::Result<serde_yaml::Mapping> {
+    m.insert(
+        serde_yaml::Value::String("format_version".to_owned()),
+        serde_yaml::Value::Number(4.into()),
+    );
+
+    let context_key = serde_yaml::Value::String("context".to_owned());
+    let after_key = serde_yaml::Value::String("after".to_owned());
+    if let Some(serde_yaml::Value::Mapping(context)) = m.get_mut(&context_key) {
+        let width = context
+            .get(&serde_yaml::Value::String("width".to_owned()))
+            .and_then(|width| width.as_u64());
+        let after = context.get(&after_key).and_then(|after| after.as_str());
+        if let (Some(width), Some(after)) = (width, after) {
+            let after: String = after.chars().take(width as usize).collect();
+            context.insert(after_key, serde_yaml::Value::String(after));
         }
     }
+
+    Ok(m)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use std::fs;
+
+    const V1_ANCHOR: &str = "
+file_path: source.py
+encoding: utf-8
+metadata:
+  foo: bar
+context:
+  before: 'def '
+  offset: 4
+  topic: func
+  after: '():'
+  width: 3
+";
+
+    #[test]
+    fn read_unversioned_anchor() {
+        let m: serde_yaml::Mapping = serde_yaml::from_str(V1_ANCHOR).unwrap();
+        assert_eq!(format_version(&m).unwrap(), 1);
+
+        let anchor = anchor_from_mapping(m, Path::new("/repo")).unwrap();
+        assert_eq!(anchor.file_path(), Path::new("/repo/source.py"));
+        assert_eq!(anchor.context().topic(), "func");
+    }
+
+    #[test]
+    fn written_anchors_are_versioned() {
+        let m: serde_yaml::Mapping = serde_yaml::from_str(V1_ANCHOR).unwrap();
+        let anchor = anchor_from_mapping(m, Path::new("/repo")).unwrap();
+
+        let m = anchor_to_mapping(&anchor, Path::new("/repo")).unwrap();
+        assert_eq!(format_version(&m).unwrap(), FORMAT_VERSION);
+    }
+
+    #[test]
+    fn long_after_context_is_cut_to_width() {
+        let legacy = V1_ANCHOR.replace("after: '():'", "after: \"():\\n    return 42\\n\"");
+        let m: serde_yaml::Mapping = serde_yaml::from_str(&legacy).unwrap();
+
+        let anchor = anchor_from_mapping(m, Path::new("/repo")).unwrap();
+        assert_eq!(anchor.context().after(), "():");
+    }
+
+    #[test]
+    fn offset_unit_is_recorded() {
+        let m: serde_yaml::Mapping = serde_yaml::from_str(V1_ANCHOR).unwrap();
+        let anchor = anchor_from_mapping(m, Path::new("/repo")).unwrap();
+
+        let mut m = anchor_to_mapping(&anchor, Path::new("/repo")).unwrap();
+        let unit = serde_yaml::Value::String("offset_unit".to_owned());
+        assert_eq!(m.get(&unit).and_then(|u| u.as_str()), Some("char"));
+
+        m.insert(unit, serde_yaml::Value::String("byte".to_owned()));
+        let err = anchor_from_mapping(m, Path::new("/repo")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn scoring_profile_is_kept() {
+        let m: serde_yaml::Mapping = serde_yaml::from_str(V1_ANCHOR).unwrap();
+        let anchor = anchor_from_mapping(m, Path::new("/repo")).unwrap();
+        assert_eq!(anchor.scoring(), None);
+
+        let anchor = anchor.with_scoring(Some("case-insensitive".to_owned()));
+        let m = anchor_to_mapping(&anchor, Path::new("/repo")).unwrap();
+        let anchor = anchor_from_mapping(m, Path::new("/repo")).unwrap();
+        assert_eq!(anchor.scoring(), Some("case-insensitive"));
+    }
+
+    #[test]
+    fn reject_newer_format_version() {
+        let mut m: serde_yaml::Mapping = serde_yaml::from_str(V1_ANCHOR).unwrap();
+        m.insert(
+            serde_yaml::Value::String("format_version".to_owned()),
+            serde_yaml::Value::Number((FORMAT_VERSION + 1).into()),
+        );
+
+        let err = anchor_from_mapping(m, Path::new("/repo")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn failed_atomic_write_leaves_original_intact() {
+        let dir = tempdir::TempDir::new("spor_serialization_tests").unwrap();
+        let path = dir.path().join("data.yml");
+        fs::write(&path, "original").unwrap();
+
+        let result = write_atomically(&path, |writer| {
+            writer.write_all(b"partial")?;
+            Err(io::Error::other("simulated crash"))
+        });
+
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "original");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn atomic_write_replaces_contents() {
+        let dir = tempdir::TempDir::new("spor_serialization_tests").unwrap();
+        let path = dir.path().join("data.yml");
+        fs::write(&path, "original").unwrap();
+
+        write_atomically(&path, |writer| writer.write_all(b"replaced")).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "replaced");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
 }
@@ -0,0 +1,222 @@
+extern crate serde_yaml;
+extern crate uuid;
+
+use std::collections::BTreeMap;
+use std::fs::{rename, DirBuilder, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anchor::Anchor;
+use super::iteration::QUARANTINE_DIR;
+use super::lock::{RepositoryLock, LOCK_TIMEOUT};
+use super::repository::AnchorId;
+use super::serialization::{
+    anchor_from_mapping, anchor_to_mapping, format_version, write_atomically, FORMAT_VERSION,
+};
+use super::store::{AnchorLoadError, AnchorStore};
+
+type Document = BTreeMap<AnchorId, serde_yaml::Mapping>;
+
+/// Stores all anchors in a single YAML document, keyed and sorted by ID.
+///
+/// Keeping everything in one file with a stable order makes changes to the
+/// anchors easy to review as ordinary diffs.
+#[derive(Debug)]
+pub struct SingleFileStore {
+    repo_root: PathBuf,
+    spor_dir: PathBuf,
+}
+
+impl SingleFileStore {
+    /// A store for the repository at `repo_root` keeping its data in the
+    /// (absolute) directory `spor_dir`.
+    pub fn new(repo_root: &Path, spor_dir: &Path) -> SingleFileStore {
+        SingleFileStore {
+            repo_root: repo_root.to_path_buf(),
+            spor_dir: spor_dir.to_path_buf(),
+        }
+    }
+
+    /// Absolute path to the anchor document.
+    ///
+    /// This deliberately doesn't use the ".yml" extension of per-anchor files.
+    fn document_path(&self) -> PathBuf {
+        self.spor_dir.join("anchors.yaml")
+    }
+
+    fn load(&self) -> io::Result<Document> {
+        let f = match File::open(self.document_path()) {
+            Ok(f) => f,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Document::new()),
+            Err(err) => return Err(err),
+        };
+
+        let document: Option<Document> = serde_yaml::from_reader(io::BufReader::new(f))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(document.unwrap_or_default())
+    }
+
+    fn save(&self, document: &Document) -> io::Result<()> {
+        write_atomically(&self.document_path(), |writer| {
+            serde_yaml::to_writer(writer, document)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        })
+    }
+
+    /// Load the document, apply `modify` to it, and save it, all while
+    /// holding the repository lock.
+    fn modify<F, T>(&self, modify: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut Document) -> io::Result<T>,
+    {
+        let _lock = RepositoryLock::acquire(&self.spor_dir, LOCK_TIMEOUT)?;
+        let mut document = self.load()?;
+        let result = modify(&mut document)?;
+        self.save(&document)?;
+        Ok(result)
+    }
+}
+
+impl AnchorStore for SingleFileStore {
+    fn get(&self, anchor_id: &AnchorId) -> io::Result<Option<Anchor>> {
+        match self.load()?.remove(anchor_id) {
+            None => Ok(None),
+            Some(m) => anchor_from_mapping(m, &self.repo_root).map(Some),
+        }
+    }
+
+    fn put(&self, anchor_id: &AnchorId, anchor: &Anchor) -> io::Result<()> {
+        let m = anchor_to_mapping(anchor, &self.repo_root)?;
+        self.modify(|document| {
+            document.insert(anchor_id.clone(), m);
+            Ok(())
+        })
+    }
+
+    fn update(&self, anchor_id: &AnchorId, anchor: &Anchor) -> io::Result<bool> {
+        let m = anchor_to_mapping(anchor, &self.repo_root)?;
+        self.modify(|document| match document.get_mut(anchor_id) {
+            Some(existing) => {
+                *existing = m;
+                Ok(true)
+            }
+            None => Ok(false),
+        })
+    }
+
+    fn delete(&self, anchor_id: &AnchorId) -> io::Result<Option<Anchor>> {
+        let repo_root = &self.repo_root;
+        self.modify(|document| match document.remove(anchor_id) {
+            None => Ok(None),
+            Some(m) => anchor_from_mapping(m, repo_root).map(Some),
+        })
+    }
+
+    fn try_iter<'a>(&'a self) -> Box<dyn Iterator<Item = Result<(AnchorId, Anchor), AnchorLoadError>> + 'a> {
+        let document = match self.load() {
+            Ok(document) => document,
+            Err(cause) => {
+                return Box::new(std::iter::once(Err(AnchorLoadError {
+                    path: self.document_path(),
+                    anchor_id: None,
+                    cause,
+                })))
+            }
+        };
+
+        Box::new(document.into_iter().map(move |(anchor_id, m)| {
+            match anchor_from_mapping(m, &self.repo_root) {
+                Ok(anchor) => Ok((anchor_id, anchor)),
+                Err(cause) => Err(AnchorLoadError {
+                    path: self.document_path(),
+                    anchor_id: Some(anchor_id),
+                    cause,
+                }),
+            }
+        }))
+    }
+
+    /// Broken entries are moved into their own file in the quarantine
+    /// directory. If the document itself is unreadable, the whole document
+    /// is quarantined under a unique name, so that it doesn't replace one
+    /// quarantined earlier.
+    fn quarantine(&self, error: &AnchorLoadError) -> io::Result<PathBuf> {
+        let quarantine_dir = self.spor_dir.join(QUARANTINE_DIR);
+        DirBuilder::new().recursive(true).create(&quarantine_dir)?;
+
+        match error.anchor_id {
+            None => {
+                let _lock = RepositoryLock::acquire(&self.spor_dir, LOCK_TIMEOUT)?;
+                let destination = quarantine_dir.join(format!("anchors.{}.yaml", uuid::Uuid::new_v4()));
+                rename(self.document_path(), &destination)?;
+                Ok(destination)
+            }
+            Some(ref anchor_id) => {
+                let destination = quarantine_dir.join(format!("{}.yml", anchor_id));
+                self.modify(|document| {
+                    let m = document.remove(anchor_id).ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", anchor_id))
+                    })?;
+                    write_atomically(&destination, |writer| {
+                        serde_yaml::to_writer(writer, &m)
+                            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+                    })
+                })?;
+                Ok(destination)
+            }
+        }
+    }
+
+    fn migrate(&self) -> io::Result<usize> {
+        let repo_root = &self.repo_root;
+        self.modify(|document| {
+            let mut migrated = 0;
+            for (anchor_id, m) in document.iter_mut() {
+                if format_version(m)? < FORMAT_VERSION {
+                    let in_context = |err: io::Error| {
+                        io::Error::new(err.kind(), format!("{}: {}", anchor_id, err))
+                    };
+                    let anchor = anchor_from_mapping(m.clone(), repo_root).map_err(&in_context)?;
+                    *m = anchor_to_mapping(&anchor, repo_root).map_err(&in_context)?;
+                    migrated += 1;
+                }
+            }
+            Ok(migrated)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempdir;
+
+    use super::*;
+    use super::super::store::conformance::check_store;
+
+    #[test]
+    fn conformance() {
+        let dir = tempdir::TempDir::new("spor_single_file_store_tests").unwrap();
+        check_store(&SingleFileStore::new(dir.path(), dir.path()), dir.path());
+    }
+
+    #[test]
+    fn quarantined_documents_are_kept() {
+        let dir = tempdir::TempDir::new("spor_single_file_store_tests").unwrap();
+        let store = SingleFileStore::new(dir.path(), dir.path());
+
+        let mut destinations = Vec::new();
+        for broken in &["[unclosed", "{unclosed"] {
+            ::std::fs::write(store.document_path(), broken).unwrap();
+            let errors: Vec<AnchorLoadError> = store.try_iter().filter_map(Result::err).collect();
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].anchor_id, None);
+            destinations.push(store.quarantine(&errors[0]).unwrap());
+        }
+
+        assert_ne!(destinations[0], destinations[1]);
+        for (destination, broken) in destinations.iter().zip(&["[unclosed", "{unclosed"]) {
+            assert_eq!(::std::fs::read_to_string(destination).unwrap(), *broken);
+        }
+        assert!(!store.document_path().exists());
+    }
+}
@@ -0,0 +1,173 @@
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anchor::Anchor;
+use super::repository::AnchorId;
+
+/// An anchor that could not be loaded from a store.
+#[derive(Debug)]
+pub struct AnchorLoadError {
+    /// The file holding the anchor data.
+    pub path: PathBuf,
+
+    /// The ID of the anchor, if it could be determined.
+    pub anchor_id: Option<AnchorId>,
+
+    /// Why the anchor couldn't be loaded.
+    pub cause: io::Error,
+}
+
+impl Display for AnchorLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.anchor_id {
+            Some(ref anchor_id) => write!(f, "{:?} (anchor {}): {}", self.path, anchor_id, self.cause),
+            None => write!(f, "{:?}: {}", self.path, self.cause),
+        }
+    }
+}
+
+impl Error for AnchorLoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.cause)
+    }
+}
+
+/// The storage behind a `Repository`.
+///
+/// Stores are responsible for persisting anchors and for serializing
+/// concurrent mutations. Paths passed to and returned from a store are
+/// absolute.
+pub trait AnchorStore: Debug {
+    /// The anchor `anchor_id`, or `None` if there is no such anchor.
+    fn get(&self, anchor_id: &AnchorId) -> io::Result<Option<Anchor>>;
+
+    /// Store `anchor` as `anchor_id`, replacing any existing anchor.
+    fn put(&self, anchor_id: &AnchorId, anchor: &Anchor) -> io::Result<()>;
+
+    /// Replace the anchor `anchor_id` with `anchor`, but only if it exists.
+    ///
+    /// The check and the write are one mutation, so an anchor deleted
+    /// concurrently isn't brought back.
+    ///
+    /// Returns: Whether there was such an anchor.
+    fn update(&self, anchor_id: &AnchorId, anchor: &Anchor) -> io::Result<bool>;
+
+    /// Delete the anchor `anchor_id`.
+    ///
+    /// Returns: The deleted anchor, or `None` if there was no such anchor.
+    fn delete(&self, anchor_id: &AnchorId) -> io::Result<Option<Anchor>>;
+
+    /// All anchors in the store, including those that couldn't be loaded.
+    fn try_iter<'a>(&'a self) -> Box<dyn Iterator<Item = Result<(AnchorId, Anchor), AnchorLoadError>> + 'a>;
+
+    /// Move the data behind a broken anchor out of the way so that it no
+    /// longer shows up as an error.
+    ///
+    /// Returns: Where the data was moved to.
+    fn quarantine(&self, error: &AnchorLoadError) -> io::Result<PathBuf> {
+        Err(io::Error::other(format!(
+            "This store can't quarantine {:?}",
+            error.path
+        )))
+    }
+
+    /// All anchors in the store that can be loaded.
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (AnchorId, Anchor)> + 'a> {
+        Box::new(self.try_iter().filter_map(Result::ok))
+    }
+
+    /// All anchored files.
+    fn files(&self) -> io::Result<Vec<PathBuf>> {
+        let mut files: Vec<PathBuf> = self
+            .iter()
+            .map(|(_id, anchor)| anchor.file_path().clone())
+            .collect();
+        files.sort();
+        files.dedup();
+        Ok(files)
+    }
+
+    /// The topic ranges of the anchors in `file_path`, ordered by offset.
+    fn locations(&self, file_path: &Path) -> io::Result<Vec<(AnchorId, Range<usize>)>> {
+        let mut locations: Vec<(AnchorId, Range<usize>)> = self
+            .iter()
+            .filter(|(_id, anchor)| anchor.file_path() == file_path)
+            .map(|(id, anchor)| {
+                let offset = anchor.context().offset();
+                let width = anchor.context().topic().chars().count();
+                (id, offset..offset + width)
+            })
+            .collect();
+        locations.sort_by(|a, b| (a.1.start, &a.0).cmp(&(b.1.start, &b.0)));
+        Ok(locations)
+    }
+
+    /// Rebuild any indices the store keeps.
+    fn reindex(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Rewrite all anchors in the current anchor format.
+    ///
+    /// Returns: The number of anchors that were upgraded.
+    fn migrate(&self) -> io::Result<usize> {
+        Ok(0)
+    }
+}
+
+/// Checks that every store implementation must pass.
+#[cfg(test)]
+pub(crate) mod conformance {
+    use super::*;
+    use anchor::Context;
+
+    fn make_anchor(root: &Path, offset: usize) -> Anchor {
+        let context = Context::new("def func():\n    return 42\n", offset, 4, 3).unwrap();
+        Anchor::new(
+            &root.join("source.py"),
+            context,
+            serde_yaml::from_str("foo: bar").unwrap(),
+            "utf-8".to_string(),
+        )
+        .unwrap()
+    }
+
+    pub fn check_store(store: &dyn AnchorStore, root: &Path) {
+        let first = "first".to_string();
+        let second = "second".to_string();
+
+        assert!(store.get(&first).unwrap().is_none());
+        assert!(store.delete(&first).unwrap().is_none());
+
+        store.put(&first, &make_anchor(root, 4)).unwrap();
+        store.put(&second, &make_anchor(root, 0)).unwrap();
+        assert_eq!(store.get(&first).unwrap().unwrap().context().topic(), "func");
+        assert_eq!(store.iter().count(), 2);
+
+        store.put(&first, &make_anchor(root, 16)).unwrap();
+        assert_eq!(store.get(&first).unwrap().unwrap().context().topic(), "retu");
+
+        assert_eq!(store.files().unwrap(), vec![root.join("source.py")]);
+        assert_eq!(
+            store.locations(&root.join("source.py")).unwrap(),
+            vec![(second.clone(), 0..4), (first.clone(), 16..20)]
+        );
+
+        assert!(store.update(&second, &make_anchor(root, 4)).unwrap());
+        assert_eq!(store.get(&second).unwrap().unwrap().context().offset(), 4);
+        store.put(&second, &make_anchor(root, 0)).unwrap();
+
+        let deleted = store.delete(&second).unwrap().unwrap();
+        assert_eq!(deleted.context().topic(), "def ");
+        assert!(store.get(&second).unwrap().is_none());
+
+        // Updating a deleted anchor doesn't bring it back.
+        assert!(!store.update(&second, &make_anchor(root, 0)).unwrap());
+        assert!(store.get(&second).unwrap().is_none());
+        assert!(store.locations(&root.join("source.py")).unwrap().iter().all(|(id, _)| *id != second));
+        assert_eq!(store.iter().map(|(id, _)| id).collect::<Vec<_>>(), vec![first]);
+    }
+}
@@ -7,11 +7,228 @@ pub fn score_func(a: char, b: char) -> f32 {
     }
 }
 
+// The penalty for opening a gap, i.e. for a gap of length 1.
+const GAP_OPEN: f32 = 2.0;
+
+// The penalty for each element that a gap is extended by. This is much
+// smaller than `GAP_OPEN` so that a block of inserted text costs little more
+// than a single insertion.
+const GAP_EXTEND: f32 = 0.125;
+
 // A standard gap-penalty function
 pub fn gap_penalty(gap: u32) -> f32 {
-    if gap == 1 {
-        2.0
+    if gap == 0 {
+        0.0
+    } else {
+        GAP_OPEN + ((gap - 1) as f32) * GAP_EXTEND
+    }
+}
+
+// Text prepared for alignment by a scoring profile.
+pub struct Normalized {
+    pub text: String,
+
+    // For each character of `text`, the index of the character of the
+    // original text it stands for.
+    pub origins: Vec<usize>,
+}
+
+// A way of comparing text: how it's prepared for alignment, and how its
+// characters are scored.
+#[derive(Debug)]
+pub struct Profile {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub normalize: fn(&str) -> Normalized,
+    pub score_func: fn(char, char) -> f32,
+}
+
+// The profile used when none is chosen.
+pub const DEFAULT_PROFILE: &str = "default";
+
+pub static PROFILES: [Profile; 4] = [
+    Profile {
+        name: "default",
+        description: "every character counts",
+        normalize: unchanged,
+        score_func,
+    },
+    Profile {
+        name: "whitespace-insensitive",
+        description: "runs of whitespace are all alike",
+        normalize: collapse_whitespace,
+        score_func: whitespace_insensitive_score_func,
+    },
+    Profile {
+        name: "case-insensitive",
+        description: "upper and lower case letters are alike",
+        normalize: unchanged,
+        score_func: case_insensitive_score_func,
+    },
+    Profile {
+        name: "comments",
+        description: "changes inside # comments matter less",
+        normalize: mark_comments,
+        score_func: comment_score_func,
+    },
+];
+
+// Find the profile called `name`.
+pub fn profile(name: &str) -> Result<&'static Profile, String> {
+    PROFILES.iter().find(|p| p.name == name).ok_or_else(|| {
+        let names: Vec<&str> = PROFILES.iter().map(|p| p.name).collect();
+        format!(
+            "Unknown scoring profile {} (expected one of: {})",
+            name,
+            names.join(", ")
+        )
+    })
+}
+
+fn unchanged(text: &str) -> Normalized {
+    Normalized {
+        text: text.to_string(),
+        origins: (0..text.chars().count()).collect(),
+    }
+}
+
+// Replace each run of whitespace with a newline if it contains one, or a
+// space if not. Lines are kept so that they can still be diffed.
+fn collapse_whitespace(text: &str) -> Normalized {
+    let mut normalized = Normalized {
+        text: String::new(),
+        origins: vec![],
+    };
+
+    let mut chars = text.chars().enumerate().peekable();
+    while let Some((index, c)) = chars.next() {
+        if !c.is_whitespace() {
+            normalized.text.push(c);
+            normalized.origins.push(index);
+            continue;
+        }
+
+        let mut newline = c == '\n';
+        while let Some(&(_, next)) = chars.peek() {
+            if !next.is_whitespace() {
+                break;
+            }
+            newline |= next == '\n';
+            chars.next();
+        }
+        normalized.text.push(if newline { '\n' } else { ' ' });
+        normalized.origins.push(index);
+    }
+
+    normalized
+}
+
+fn whitespace_insensitive_score_func(a: char, b: char) -> f32 {
+    if a.is_whitespace() && b.is_whitespace() {
+        score_func(a, a)
+    } else {
+        score_func(a, b)
+    }
+}
+
+fn case_insensitive_score_func(a: char, b: char) -> f32 {
+    if a.to_lowercase().eq(b.to_lowercase()) {
+        score_func(a, a)
     } else {
-        (gap as f32) * gap_penalty(1)
+        score_func(a, b)
+    }
+}
+
+// Characters in comments are stood in for by characters from here up, so
+// that the scoring function can tell them apart.
+const COMMENT_BASE: u32 = 0xF0000;
+
+// Characters above this can't be marked, and are left as they are.
+const MAX_MARKED: u32 = 0xFFFE;
+
+// How much mismatches inside comments count, compared to elsewhere.
+const COMMENT_MISMATCH_WEIGHT: f32 = 1.0 / 3.0;
+
+// Mark the characters of `#` comments, from the `#` to the end of the line.
+fn mark_comments(text: &str) -> Normalized {
+    let mut in_comment = false;
+    let marked = text
+        .chars()
+        .map(|c| {
+            if c == '\n' {
+                in_comment = false;
+            } else if c == '#' {
+                in_comment = true;
+            }
+
+            if in_comment && (c as u32) < MAX_MARKED {
+                std::char::from_u32(COMMENT_BASE + c as u32).unwrap_or(c)
+            } else {
+                c
+            }
+        })
+        .collect();
+
+    Normalized {
+        text: marked,
+        origins: (0..text.chars().count()).collect(),
+    }
+}
+
+// The character marked by `mark_comments`, and whether it was in a comment.
+fn unmark(c: char) -> (char, bool) {
+    let code = c as u32;
+    if (COMMENT_BASE..COMMENT_BASE + MAX_MARKED).contains(&code) {
+        (std::char::from_u32(code - COMMENT_BASE).unwrap_or(c), true)
+    } else {
+        (c, false)
+    }
+}
+
+fn comment_score_func(a: char, b: char) -> f32 {
+    let (a, a_in_comment) = unmark(a);
+    let (b, b_in_comment) = unmark(b);
+    let score = score_func(a, b);
+    if score < 0.0 && (a_in_comment || b_in_comment) {
+        score * COMMENT_MISMATCH_WEIGHT
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whitespace_runs_collapse() {
+        let normalized = collapse_whitespace("f(a,  b)\n    \tc");
+        assert_eq!(normalized.text, "f(a, b)\nc");
+        assert_eq!(normalized.origins, vec![0, 1, 2, 3, 4, 6, 7, 8, 14]);
+    }
+
+    #[test]
+    fn case_is_ignored() {
+        assert_eq!(case_insensitive_score_func('a', 'A'), 3.0);
+        assert_eq!(case_insensitive_score_func('a', 'b'), -3.0);
+    }
+
+    #[test]
+    fn comment_mismatches_count_less() {
+        let normalized = mark_comments("x = 1  # one\ny = 2");
+        let chars: Vec<char> = normalized.text.chars().collect();
+        assert_eq!(chars[0], 'x');
+        assert_eq!(unmark(chars[7]), ('#', true));
+        assert_eq!(unmark(chars[12]), ('\n', false));
+
+        assert_eq!(comment_score_func(chars[9], chars[9]), 3.0);
+        assert_eq!(comment_score_func(chars[9], chars[10]), -1.0);
+        assert_eq!(comment_score_func(chars[0], chars[13]), -3.0);
+    }
+
+    #[test]
+    fn unknown_profile() {
+        assert!(profile(DEFAULT_PROFILE).is_ok());
+        assert!(profile("bogus").unwrap_err().contains("whitespace-insensitive"));
     }
 }
@@ -0,0 +1,189 @@
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use alignment::align::Align;
+use anchor::{Anchor, Context};
+use file_io::read_file;
+use updating::{update_with_options, UpdateError, UpdateOptions};
+
+/// How an anchor relates to the current contents of its source file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    /// The context is unchanged at the same offset.
+    Intact,
+
+    /// The context is unchanged, but at a new offset.
+    Moved,
+
+    /// The topic has changed.
+    TopicModified,
+
+    /// The topic is unchanged, but the text around it has changed.
+    ContextModified,
+
+    /// The anchor can't be found with enough confidence.
+    Lost,
+
+    /// The source file doesn't exist.
+    FileMissing,
+}
+
+impl Status {
+    pub const ALL: [Status; 6] = [
+        Status::Intact,
+        Status::Moved,
+        Status::TopicModified,
+        Status::ContextModified,
+        Status::Lost,
+        Status::FileMissing,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Status::Intact => "intact",
+            Status::Moved => "moved",
+            Status::TopicModified => "topic-modified",
+            Status::ContextModified => "context-modified",
+            Status::Lost => "lost",
+            Status::FileMissing => "file-missing",
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Status {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Status, String> {
+        Status::ALL
+            .iter()
+            .find(|status| status.name() == s)
+            .cloned()
+            .ok_or_else(|| {
+                let names: Vec<&str> = Status::ALL.iter().map(Status::name).collect();
+                format!("Unknown anchor status {} (expected one of: {})", s, names.join(", "))
+            })
+    }
+}
+
+/// Work out the status of `anchor`.
+///
+/// Anchors whose context can't be found unchanged are aligned with their
+/// source file, using `align` and `options`, just as `update` would.
+pub fn classify(anchor: &Anchor, align: &Align, options: &UpdateOptions) -> io::Result<Status> {
+    if !anchor.file_path().exists() {
+        return Ok(Status::FileMissing);
+    }
+
+    let full_text = read_file(anchor.file_path(), anchor.encoding())?;
+    let ctxt = anchor.context();
+
+    let current = Context::new(
+        &full_text,
+        ctxt.offset(),
+        ctxt.topic().chars().count(),
+        ctxt.width(),
+    );
+    if let Ok(current) = current {
+        if current.before() == ctxt.before()
+            && current.topic() == ctxt.topic()
+            && current.after() == ctxt.after()
+        {
+            return Ok(Status::Intact);
+        }
+    }
+
+    if full_text.contains(ctxt.full_text().as_str()) {
+        return Ok(Status::Moved);
+    }
+
+    match update_with_options(anchor, align, options) {
+        Ok(ref updated) if updated.context().topic() == ctxt.topic() => Ok(Status::ContextModified),
+        Ok(_) => Ok(Status::TopicModified),
+        Err(UpdateError::Io(kind, description)) => Err(io::Error::new(kind, description)),
+        Err(UpdateError::EncodingError(description)) => {
+            Err(io::Error::new(io::ErrorKind::InvalidData, description))
+        }
+        Err(UpdateError::UnknownProfile(description)) => {
+            Err(io::Error::new(io::ErrorKind::InvalidInput, description))
+        }
+        Err(UpdateError::NoAlignments)
+        | Err(UpdateError::InvalidAlignment)
+        | Err(UpdateError::LowConfidence { .. })
+        | Err(UpdateError::Orphaned) => Ok(Status::Lost),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate serde_yaml;
+    extern crate tempdir;
+
+    use super::*;
+    use alignment::linear_space::align;
+    use std::fs;
+
+    const SOURCE: &str = "import os\n\ndef func():\n    return 1\n\nprint(func())\n";
+
+    // The status of an anchor on "func" after the source is changed to
+    // `modified`, or deleted if that's `None`.
+    fn status_after(modified: Option<&str>) -> Status {
+        let dir = tempdir::TempDir::new("spor_status_tests").unwrap();
+        let path = dir.path().join("source.py");
+
+        let context = Context::new(SOURCE, 15, 4, 3).unwrap();
+        let anchor = Anchor::new(&path, context, serde_yaml::Value::Null, "utf-8".to_string()).unwrap();
+
+        if let Some(modified) = modified {
+            fs::write(&path, modified).unwrap();
+        }
+
+        classify(&anchor, &align, &UpdateOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn intact() {
+        assert_eq!(status_after(Some(SOURCE)), Status::Intact);
+    }
+
+    #[test]
+    fn moved() {
+        assert_eq!(status_after(Some(&format!("# x\n{}", SOURCE))), Status::Moved);
+    }
+
+    #[test]
+    fn topic_modified() {
+        let modified = SOURCE.replace("func", "funk");
+        assert_eq!(status_after(Some(&modified)), Status::TopicModified);
+    }
+
+    #[test]
+    fn context_modified() {
+        let modified = SOURCE.replace("def func():", "def func(x):");
+        assert_eq!(status_after(Some(&modified)), Status::ContextModified);
+    }
+
+    #[test]
+    fn lost() {
+        assert_eq!(status_after(Some("class Widget:\n    pass\n")), Status::Lost);
+    }
+
+    #[test]
+    fn file_missing() {
+        assert_eq!(status_after(None), Status::FileMissing);
+    }
+
+    #[test]
+    fn statuses_by_name() {
+        for status in Status::ALL.iter() {
+            assert_eq!(status.name().parse::<Status>(), Ok(*status));
+        }
+        assert!("bogus".parse::<Status>().is_err());
+    }
+}
@@ -1,14 +1,73 @@
+extern crate diff;
+
 use std::error::Error;
+use std::fmt;
 
-use alignment::align::{Align, AlignmentCell};
+use alignment::align::{Align, Alignment, AlignmentCell, MAX_ALIGNMENTS};
 use anchor::{Anchor, Context};
 use file_io::read_file;
-use scoring::{gap_penalty, score_func};
+use scoring::{self, gap_penalty, Profile};
+
+/// Options controlling how `update` searches for an anchor's new location.
+#[derive(Clone, Debug)]
+pub struct UpdateOptions {
+    /// The score, as a fraction of a perfect match, below which a search
+    /// window is considered to have missed the anchor.
+    pub min_confidence: f32,
+
+    /// The confidence, i.e. the score as a fraction of a perfect match,
+    /// below which an anchor is not updated. See `UpdateError::LowConfidence`.
+    pub threshold: f32,
+
+    /// How far on either side of the anchor's previous location to search
+    /// before searching the whole file, as multiples of the length of the
+    /// anchor's context. Each window is tried in turn.
+    pub window_scales: Vec<usize>,
+
+    /// Whether to diff the lines of the context with the file, and search
+    /// the regions where they match, before searching the whole file.
+    pub line_diff: bool,
+
+    /// The scoring profile to use for every anchor, rather than the one each
+    /// anchor was given.
+    pub scoring: Option<&'static Profile>,
+}
+
+impl Default for UpdateOptions {
+    fn default() -> UpdateOptions {
+        UpdateOptions {
+            min_confidence: 0.8,
+            threshold: 0.5,
+            window_scales: vec![2, 4],
+            line_diff: true,
+            scoring: None,
+        }
+    }
+}
 
 /// Update an anchor based on the current contents of its source file.
 pub fn update(anchor: &Anchor, align: &Align) -> Result<Anchor, UpdateError> {
+    update_with_options(anchor, align, &UpdateOptions::default())
+}
+
+/// Like `update`, but searching as described by `options`.
+pub fn update_with_options(
+    anchor: &Anchor,
+    align: &Align,
+    options: &UpdateOptions,
+) -> Result<Anchor, UpdateError> {
+    update_with_confidence(anchor, align, options).map(|(updated, _)| updated)
+}
+
+/// Like `update_with_options`, but also giving the confidence of the update,
+/// i.e. the alignment score as a fraction of a perfect match.
+pub fn update_with_confidence(
+    anchor: &Anchor,
+    align: &Align,
+    options: &UpdateOptions,
+) -> Result<(Anchor, f32), UpdateError> {
     let contents = read_file(anchor.file_path(), anchor.encoding())?;
-    _update(anchor, &contents, align)
+    _update(anchor, &contents, align, options)
 }
 
 /// The main update implementation.
@@ -19,44 +78,36 @@ fn _update(
     anchor: &Anchor,
     full_text: &str,
     align: &Align,
-) -> Result<Anchor, UpdateError> {
+    options: &UpdateOptions,
+) -> Result<(Anchor, f32), UpdateError> {
     let ctxt = anchor.context();
 
-    let (_, alignments) = align(&ctxt.full_text(), &full_text, &score_func, &gap_penalty);
-
-    let alignment = match alignments.first() {
-        Some(a) => Ok(a),
-        None => Err(UpdateError::NoAlignments),
-    }?;
-
-    let anchor_offset = (ctxt.offset() as usize) - ctxt.before().len();
+    let profile = match options.scoring {
+        Some(profile) => profile,
+        None => scoring::profile(anchor.scoring().unwrap_or(scoring::DEFAULT_PROFILE))
+            .map_err(UpdateError::UnknownProfile)?,
+    };
 
     // Determine the new location of the topic in the modified source
-    let source_indices: Vec<usize> = alignment
-        .into_iter()
-        // Look for all cells in the alignment where both sides contribute.
-        .filter_map(|a| match a {
-            AlignmentCell::Both { left: l, right: r } => Some((l, r)),
-            _ => None,
-        })
-        // Keep only the cells where the anchor index is in the topic (i.e. no
-        // in the before or after part of the context)
-        .filter(|(a_idx, _)| index_in_topic(*a_idx + anchor_offset, &anchor))
-        // From those cells, extract the index in the modified source.
-        .map(|(_, s_idx)| *s_idx)
-        .collect();
+    let (confidence, source_indices) = search(ctxt, full_text, align, options, profile)?;
+    if confidence < options.threshold {
+        return Err(UpdateError::LowConfidence {
+            score: confidence,
+            threshold: options.threshold,
+        });
+    }
 
-    let new_topic_offset = match source_indices.first() {
-        Some(index) => Ok(index),
-        None => Err(UpdateError::InvalidAlignment),
+    let (new_topic_offset, new_topic_end) = match (source_indices.first(), source_indices.last()) {
+        (Some(first), Some(last)) => Ok((*first, *last)),
+        _ => Err(UpdateError::InvalidAlignment),
     }?;
 
     // Given the new topic offset and size, we can create a new context and
-    // anchor.
+    // anchor. The topic covers any text inserted into it.
     let context = Context::new(
         full_text,
-        *new_topic_offset,
-        source_indices.len(),
+        new_topic_offset,
+        new_topic_end - new_topic_offset + 1,
         anchor.context().width(),
     )?;
 
@@ -65,12 +116,240 @@ fn _update(
         context,
         anchor.metadata().clone(),
         anchor.encoding().clone(),
-    )?;
+    )?
+    .with_scoring(anchor.scoring().map(str::to_owned));
+
+    Ok((updated, confidence))
+}
+
+/// Align the context `ctxt` with `full_text`, starting near its previous
+/// location and widening the search until it's found with enough confidence.
+///
+/// Both texts are normalized and scored as `profile` says.
+///
+/// Returns: The confidence of the alignment (its score as a fraction of a
+/// perfect match), and the indices in `full_text` that the topic aligns
+/// with.
+fn search(
+    ctxt: &Context,
+    full_text: &str,
+    align: &Align,
+    options: &UpdateOptions,
+    profile: &Profile,
+) -> Result<(f32, Vec<usize>), UpdateError> {
+    let context = (profile.normalize)(&ctxt.full_text());
+    let text = (profile.normalize)(full_text);
+    let context_len = context.origins.len();
+    let text_len = text.origins.len();
+
+    // Where the context was, in the normalized text.
+    let old_start = ctxt.offset().saturating_sub(ctxt.before().chars().count());
+    let context_start = text.origins.partition_point(|origin| *origin < old_start);
+
+    let score_func = &profile.score_func;
+    let perfect_score: f32 = context.text.chars().map(|c| score_func(c, c)).sum();
+    let confidence = |score: f32| {
+        if perfect_score > 0.0 {
+            score / perfect_score
+        } else {
+            0.0
+        }
+    };
+
+    // Align the context with the normalized text from `start` up to `end`,
+    // giving the score and the alignments of the original texts.
+    let align_region = |start: usize, end: usize| {
+        let region = char_slice(&text.text, start, end);
+        let (score, alignments) = align(&context.text, region, score_func, &gap_penalty);
+        let alignments: Vec<Alignment> = alignments
+            .iter()
+            .map(|alignment| restore(alignment, &context.origins, &text.origins[start..]))
+            .collect();
+        (score, alignments)
+    };
+
+    // Like `align_region`, but giving the topic indices only if the context is
+    // found confidently.
+    let search_region = |start: usize, end: usize| {
+        let (score, alignments) = align_region(start, end);
+        if confidence(score) >= options.min_confidence {
+            nearest_topic(ctxt, &alignments, ctxt.offset()).map(|indices| (score, indices))
+        } else {
+            None
+        }
+    };
+
+    let mut whole_file = false;
+    for scale in &options.window_scales {
+        let padding = context_len * scale;
+        let start = context_start.saturating_sub(padding);
+        let end = (context_start + context_len + padding).min(text_len);
+        if start == 0 && end == text_len {
+            whole_file = true;
+            break;
+        }
+
+        if let Some((score, indices)) = search_region(start, end) {
+            return Ok((confidence(score), indices));
+        }
+    }
+
+    if options.line_diff && !whole_file {
+        // The best of the regions, or the nearest of equally good ones.
+        let distance = |indices: &[usize]| (indices[0] as isize - ctxt.offset() as isize).abs();
+        let mut best: Option<(f32, Vec<usize>)> = None;
+        for (start, end) in candidate_regions(&context.text, &text.text, context_len) {
+            if let Some((score, indices)) = search_region(start, end) {
+                let better = match best {
+                    Some((best_score, ref best_indices)) => {
+                        score > best_score
+                            || (score == best_score && distance(&indices) < distance(best_indices))
+                    }
+                    None => true,
+                };
+                if better {
+                    best = Some((score, indices));
+                }
+            }
+        }
+
+        if let Some((score, indices)) = best {
+            return Ok((confidence(score), indices));
+        }
+    }
+
+    let (score, mut alignments) = align_region(0, text_len);
+    if alignments.is_empty() {
+        return Err(UpdateError::NoAlignments);
+    }
+
+    // Aligners return at most `MAX_ALIGNMENTS` alignments, earliest first, so
+    // the nearest of more equally good ones may have been dropped. Widen a
+    // window around the old location until it finds one as good.
+    if alignments.len() >= MAX_ALIGNMENTS {
+        let mut padding = context_len.max(1);
+        loop {
+            let start = context_start.saturating_sub(padding);
+            let end = (context_start + context_len + padding).min(text_len);
+            if start == 0 && end == text_len {
+                break;
+            }
+
+            let (window_score, window_alignments) = align_region(start, end);
+            if window_score >= score && !window_alignments.is_empty() {
+                alignments = window_alignments;
+                break;
+            }
+            padding *= 2;
+        }
+    }
+
+    nearest_topic(ctxt, &alignments, ctxt.offset())
+        .map(|indices| (confidence(score), indices))
+        .ok_or(UpdateError::InvalidAlignment)
+}
+
+/// Map an alignment of normalized texts back to the original texts, given
+/// the origins of the characters on each side.
+fn restore(alignment: &Alignment, left_origins: &[usize], right_origins: &[usize]) -> Alignment {
+    alignment
+        .iter()
+        .map(|cell| match *cell {
+            AlignmentCell::Both { left, right } => AlignmentCell::Both {
+                left: left_origins[left],
+                right: right_origins[right],
+            },
+            AlignmentCell::RightGap { left } => AlignmentCell::RightGap {
+                left: left_origins[left],
+            },
+            AlignmentCell::LeftGap { right } => AlignmentCell::LeftGap {
+                right: right_origins[right],
+            },
+        })
+        .collect()
+}
+
+/// The regions of `full_text` that might contain `context_text`, found by
+/// diffing their lines.
+///
+/// Lines of `full_text` that match lines of the context are grouped into
+/// regions, each padded by `padding` characters on either side so that
+/// partial lines at the edges of the context are covered.
+///
+/// Returns: The start and end character offsets of each region.
+fn candidate_regions(context_text: &str, full_text: &str, padding: usize) -> Vec<(usize, usize)> {
+    let context_lines: Vec<&str> = context_text.split_inclusive('\n').collect();
+    let text_lines: Vec<&str> = full_text.split_inclusive('\n').collect();
+
+    let mut line_starts = vec![0];
+    for line in &text_lines {
+        let last = line_starts[line_starts.len() - 1];
+        line_starts.push(last + line.chars().count());
+    }
+    let text_len = line_starts[text_lines.len()];
+
+    let mut regions: Vec<(usize, usize)> = vec![];
+    let mut line = 0;
+    for result in diff::slice(&context_lines, &text_lines) {
+        match result {
+            diff::Result::Left(_) => continue,
+            diff::Result::Right(_) => {
+                line += 1;
+                continue;
+            }
+            diff::Result::Both(_, _) => {}
+        }
+
+        let start = line_starts[line].saturating_sub(padding);
+        let end = (line_starts[line + 1] + padding).min(text_len);
+        match regions.last_mut() {
+            Some(region) if start <= region.1 => region.1 = end,
+            _ => regions.push((start, end)),
+        }
+        line += 1;
+    }
+
+    regions
+}
+
+/// Of the topic locations given by `alignments`, the one starting nearest to
+/// `offset`. Ties go to the earliest alignment, so results are reproducible
+/// however many equally good alignments there are.
+fn nearest_topic(ctxt: &Context, alignments: &[Alignment], offset: usize) -> Option<Vec<usize>> {
+    alignments
+        .iter()
+        .map(|alignment| topic_indices(ctxt, alignment))
+        .filter(|indices| !indices.is_empty())
+        .min_by_key(|indices| (indices[0] as isize - offset as isize).abs())
+}
+
+/// The indices in the aligned text of the cells of `alignment` that align
+/// with the topic of `ctxt`.
+fn topic_indices(ctxt: &Context, alignment: &Alignment) -> Vec<usize> {
+    let anchor_offset = (ctxt.offset() as usize) - ctxt.before().chars().count();
+
+    alignment
+        .iter()
+        // Look for all cells in the alignment where both sides contribute.
+        .filter_map(|a| match a {
+            AlignmentCell::Both { left: l, right: r } => Some((l, r)),
+            _ => None,
+        })
+        // Keep only the cells where the anchor index is in the topic (i.e. no
+        // in the before or after part of the context)
+        .filter(|(a_idx, _)| index_in_topic(*a_idx + anchor_offset, ctxt))
+        // From those cells, extract the index in the modified source.
+        .map(|(_, s_idx)| *s_idx)
+        .collect()
+}
 
-    Ok(updated)
+/// The characters of `text` from `start` up to `end`.
+fn char_slice(text: &str, start: usize, end: usize) -> &str {
+    let byte_index = |n| text.char_indices().nth(n).map_or(text.len(), |(i, _)| i);
+    &text[byte_index(start)..byte_index(end)]
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum UpdateError {
     // No alignments could be found
     NoAlignments,
@@ -80,7 +359,35 @@ pub enum UpdateError {
 
     Io(std::io::ErrorKind, String),
 
-    EncodingError(String)
+    EncodingError(String),
+
+    // The anchor's scoring profile doesn't exist
+    UnknownProfile(String),
+
+    // The best alignment is too poor a match to trust
+    LowConfidence { score: f32, threshold: f32 },
+
+    // The anchor's file is gone and its context wasn't found in any other
+    // file. See `relocation::update_or_relocate`.
+    Orphaned,
+}
+
+impl fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UpdateError::NoAlignments => write!(f, "no alignments found"),
+            UpdateError::InvalidAlignment => write!(f, "topic not found in alignment"),
+            UpdateError::Io(_, ref description) => write!(f, "{}", description),
+            UpdateError::EncodingError(ref description) => write!(f, "{}", description),
+            UpdateError::UnknownProfile(ref description) => write!(f, "{}", description),
+            UpdateError::LowConfidence { score, threshold } => write!(
+                f,
+                "low confidence: {:.2} is below the threshold of {:.2}",
+                score, threshold
+            ),
+            UpdateError::Orphaned => write!(f, "file is missing and the anchor wasn't found elsewhere"),
+        }
+    }
 }
 
 impl From<std::io::Error> for UpdateError {
@@ -89,10 +396,10 @@ impl From<std::io::Error> for UpdateError {
     }
 }
 
-// Determines if an index is in the topic of an anchor
-fn index_in_topic(index: usize, anchor: &Anchor) -> bool {
-    (index >= anchor.context().offset() as usize)
-        && (index < anchor.context().offset() as usize + anchor.context().topic().len())
+// Determines if an index is in the topic of an anchor's context
+fn index_in_topic(index: usize, ctxt: &Context) -> bool {
+    (index >= ctxt.offset() as usize)
+        && (index < ctxt.offset() as usize + ctxt.topic().chars().count())
 }
 
 #[cfg(test)]
@@ -100,8 +407,12 @@ mod tests {
     extern crate ndarray;
     extern crate serde_yaml;
 
+    use super::super::alignment::align::{GapPenaltyFunction, ScoringFunction};
     use super::super::alignment::smith_waterman::align;
+    use super::super::alignment::{linear_space, registry, semi_global};
     use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
     use std::path::PathBuf;
 
     #[test]
@@ -121,8 +432,242 @@ mod tests {
         )
         .unwrap();
 
-        let updated_anchor = _update(&anchor, final_text, &align).unwrap();
+        let (updated_anchor, confidence) =
+            _update(&anchor, final_text, &align, &UpdateOptions::default()).unwrap();
 
         assert_eq!(updated_anchor.context().offset(), 5);
+        assert_eq!(confidence, 1.0);
+    }
+
+    #[test]
+    fn multibyte_context_is_updated() {
+        let anchor = make_anchor("# ÿöü\nfunc()", 6, 4);
+        assert_eq!(anchor.context().before(), "öü\n");
+
+        let (updated, confidence) = _update(
+            &anchor,
+            "# añadido\n# ÿöü\nfunc()",
+            &align,
+            &UpdateOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(updated.context().offset(), 16);
+        assert_eq!(updated.context().topic(), "func");
+        assert_eq!(confidence, 1.0);
+    }
+
+    fn make_anchor(text: &str, offset: usize, width: usize) -> Anchor {
+        let context = Context::new(text, offset, width, 3).unwrap();
+        Anchor::new(
+            &PathBuf::from("/foo/bar"),
+            context,
+            serde_yaml::Value::Null,
+            "utf-8".to_string(),
+        )
+        .unwrap()
+    }
+
+    /// Update `anchor`, returning the result and the lengths of the texts
+    /// that were searched.
+    fn windowed_update(anchor: &Anchor, text: &str) -> (Anchor, Vec<usize>) {
+        let searched = Rc::new(RefCell::new(vec![]));
+        let recorder = searched.clone();
+        let recording_align =
+            move |a: &str, b: &str, s: &ScoringFunction, g: &GapPenaltyFunction| {
+                recorder.borrow_mut().push(b.chars().count());
+                align(a, b, s, g)
+            };
+
+        let updated = _update(anchor, text, &recording_align, &UpdateOptions::default()).unwrap().0;
+        let searched = searched.borrow().clone();
+        (updated, searched)
+    }
+
+    #[test]
+    fn small_moves_search_a_window() {
+        let filler = "-".repeat(200);
+        let initial_text = format!("{}\ndef func():", filler);
+        let anchor = make_anchor(&initial_text, 205, 4);
+
+        let final_text = format!("# new\n{}", initial_text);
+        let (updated, searched) = windowed_update(&anchor, &final_text);
+        assert_eq!(updated.context().offset(), 211);
+        assert_eq!(searched, vec![36]);
+    }
+
+    #[test]
+    fn topic_includes_inserted_block() {
+        let initial_text = "import os\n\ndef func():\n    x = 1\n    return x\n\nprint(func())\n";
+        let topic = "def func():\n    x = 1\n    return x\n";
+        let anchor = make_anchor(initial_text, 11, topic.len());
+
+        let inserted: String = (0..10).map(|n| format!("    y{} = x + {}\n", n, n)).collect();
+        let final_text = initial_text.replace("    return", &format!("{}    return", inserted));
+        let (updated, _) = windowed_update(&anchor, &final_text);
+
+        assert_eq!(updated.context().offset(), 11);
+        assert_eq!(
+            updated.context().topic(),
+            &topic.replace("    return", &format!("{}    return", inserted))
+        );
+    }
+
+    #[test]
+    fn nearest_of_identical_matches_is_chosen() {
+        let initial_text = "x = 1\nasdf\n".repeat(10);
+        let offset = initial_text.len() - 5;
+        let anchor = make_anchor(&initial_text, offset, 4);
+
+        let final_text = format!("#\n{}", initial_text);
+        let (updated, _) = windowed_update(&anchor, &final_text);
+        assert_eq!(updated.context().offset(), offset + 2);
+
+        // The whole file is searched if there's no window, but the nearest
+        // match still wins.
+        let options = UpdateOptions {
+            window_scales: vec![],
+            line_diff: false,
+            ..UpdateOptions::default()
+        };
+        let updated = _update(&anchor, &final_text, &align, &options).unwrap().0;
+        assert_eq!(updated.context().offset(), offset + 2);
+    }
+
+    #[test]
+    fn nearest_match_survives_alignment_limit() {
+        // More identical matches than aligners return, with the anchor near
+        // the end of the file.
+        let initial_text = "x = 1\nasdf\n".repeat(MAX_ALIGNMENTS + 50);
+        let offset = initial_text.len() - 5;
+        let anchor = make_anchor(&initial_text, offset, 4);
+
+        let final_text = format!("#\n{}", initial_text);
+        let options = UpdateOptions {
+            window_scales: vec![],
+            line_diff: false,
+            ..UpdateOptions::default()
+        };
+        let aligners: [&Align; 3] = [&align, &linear_space::align, &semi_global::align];
+        for aligner in aligners.iter() {
+            let updated = _update(&anchor, &final_text, *aligner, &options).unwrap().0;
+            assert_eq!(updated.context().offset(), offset + 2);
+        }
+    }
+
+    #[test]
+    fn distant_moves_widen_search() {
+        let filler = "-".repeat(200);
+        let initial_text = format!("{}\ndef func():", filler);
+        let anchor = make_anchor(&initial_text, 205, 4);
+
+        let final_text = format!("def func():\n{}\n{}", filler, filler);
+        let (updated, searched) = windowed_update(&anchor, &final_text);
+        assert_eq!(updated.context().offset(), 4);
+        assert_eq!(updated.context().topic(), "func");
+        assert_eq!(searched, vec![50, 90, 413]);
+    }
+
+    #[test]
+    fn whitespace_insensitive_profile_follows_reformatting() {
+        let initial_text = "def f(a,b):\n  return g(a,b)\n";
+        let anchor = make_anchor(initial_text, 14, 13)
+            .with_scoring(Some("whitespace-insensitive".to_owned()));
+
+        let final_text = "def f(a, b):\n    return g(\n        a, b\n    )\n";
+        let updated = _update(&anchor, final_text, &align, &UpdateOptions::default()).unwrap().0;
+        assert_eq!(updated.context().offset(), 17);
+        assert_eq!(updated.context().topic(), "return g(\n        a, b\n    )");
+        assert_eq!(updated.scoring(), Some("whitespace-insensitive"));
+    }
+
+    #[test]
+    fn global_alignment_updates_anchors_in_long_files() {
+        let lines: Vec<String> = (0..300).map(|n| format!("x{} = {}\n", n, n)).collect();
+        let block = "def func():\n    return 42\n";
+        let initial_text = format!("{}{}{}", lines[..150].concat(), block, lines[150..].concat());
+        let offset = lines[..150].concat().len() + 4;
+        let context = Context::new(&initial_text, offset, 4, 10).unwrap();
+        let anchor = Anchor::new(
+            &PathBuf::from("/foo/bar"),
+            context,
+            serde_yaml::Value::Null,
+            "utf-8".to_string(),
+        )
+        .unwrap();
+
+        // Moved well away from its old location, and with its context edited.
+        let inserted = "# A new comment\n".repeat(50);
+        let final_text = format!("{}{}", inserted, initial_text.replace("func()", "func(a)"));
+        let align = registry::by_name("needleman-wunsch").unwrap();
+        let (updated, confidence) =
+            _update(&anchor, &final_text, &align, &UpdateOptions::default()).unwrap();
+        assert_eq!(updated.context().offset(), inserted.len() + offset);
+        assert_eq!(updated.context().topic(), "func");
+        assert!(confidence > 0.8);
+    }
+
+    #[test]
+    fn rewritten_text_has_low_confidence() {
+        let initial_text = "def func():";
+        let anchor = make_anchor(initial_text, 4, 4);
+
+        let final_text = "class Widget:\n    pass\n";
+        let result = _update(&anchor, final_text, &align, &UpdateOptions::default());
+        match result {
+            Err(UpdateError::LowConfidence { score, threshold }) => {
+                assert!(score < threshold);
+                assert_eq!(threshold, 0.5);
+            }
+            other => panic!("expected low confidence, got {:?}", other),
+        }
+
+        // Any alignment at all is accepted without a threshold.
+        let options = UpdateOptions {
+            threshold: 0.0,
+            ..UpdateOptions::default()
+        };
+        assert!(_update(&anchor, final_text, &align, &options).is_ok());
+    }
+
+    #[test]
+    fn options_override_anchor_profile() {
+        let anchor = make_anchor("asdf", 0, 4).with_scoring(Some("bogus".to_owned()));
+
+        let result = _update(&anchor, "ASDF", &align, &UpdateOptions::default());
+        assert!(matches!(result, Err(UpdateError::UnknownProfile(_))));
+
+        let options = UpdateOptions {
+            scoring: Some(scoring::profile("case-insensitive").unwrap()),
+            ..UpdateOptions::default()
+        };
+        let updated = _update(&anchor, "qwer\nASDF", &align, &options).unwrap().0;
+        assert_eq!(updated.context().topic(), "ASDF");
+    }
+
+    #[test]
+    fn line_diff_finds_distant_moves() {
+        let lines: Vec<String> = (0..400).map(|n| format!("x{} = {}\n", n, n)).collect();
+        let block = "def func():\n    return 42\n";
+
+        let initial_text = format!("{}{}", lines[..400].concat(), block);
+        let offset = lines[..400].concat().len() + 12;
+        let context = Context::new(&initial_text, offset, 14, 20).unwrap();
+        let anchor = Anchor::new(
+            &PathBuf::from("/foo/bar"),
+            context,
+            serde_yaml::Value::Null,
+            "utf-8".to_string(),
+        )
+        .unwrap();
+
+        let final_text = format!("{}{}{}", lines[..100].concat(), block, lines[100..400].concat());
+        let (updated, searched) = windowed_update(&anchor, &final_text);
+        assert_eq!(updated.context().offset(), lines[..100].concat().len() + 12);
+        assert_eq!(updated.context().topic(), "    return 42\n");
+
+        // Only the two windows and the region around the moved lines are
+        // searched.
+        assert_eq!(searched.len(), 3);
+        assert!(searched[2] < 200);
     }
 }
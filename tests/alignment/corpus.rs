@@ -0,0 +1,174 @@
+// A corpus of edits comparing the alignment algorithms in
+// `spor::alignment::registry`.
+//
+// Each case anchors some text, changes the file, and lists the algorithms
+// that find the topic where a person would expect it. Every algorithm is
+// tried on every case, so the corpus shows where each one wins and where it
+// falls short.
+
+extern crate serde_yaml;
+extern crate spor;
+extern crate tempdir;
+
+use std::cell::Cell;
+use std::fs;
+use std::rc::Rc;
+
+use spor::alignment::registry::ALGORITHMS;
+use spor::anchor::{Anchor, Context};
+use spor::scoring::{gap_penalty, score_func};
+use spor::updating::{update_with_options, UpdateOptions};
+
+struct Case {
+    name: &'static str,
+    before: &'static str,
+    topic: &'static str,
+    after: &'static str,
+    context_width: usize,
+
+    // The file after the edit.
+    modified: &'static str,
+
+    // Where the topic is expected to be in `modified`.
+    expected_offset: usize,
+    expected_topic: &'static str,
+
+    // The algorithms that find the expected topic.
+    winners: &'static [&'static str],
+}
+
+const CORPUS: &[Case] = &[
+    Case {
+        name: "unchanged text moved down the file",
+        before: "import os\n\n",
+        topic: "def main():\n    print(os.getcwd())\n",
+        after: "\nmain()\n",
+        context_width: 10,
+        modified: "#!/usr/bin/env python\nimport os\n\ndef main():\n    print(os.getcwd())\n\nmain()\n",
+        expected_offset: 33,
+        expected_topic: "def main():\n    print(os.getcwd())\n",
+        winners: &["local", "smith-waterman", "semi-global", "needleman-wunsch", "tokens", "exact"],
+    },
+    Case {
+        // Local alignment drops the final parenthesis, since matching it
+        // costs more than it scores.
+        name: "edit near the end of a topic at the end of the file",
+        before: "import os\n\n",
+        topic: "print(os.getcwd())",
+        after: "",
+        context_width: 10,
+        modified: "import os\n\nprint(os.getcwd(), file=sys.stderr)",
+        expected_offset: 11,
+        expected_topic: "print(os.getcwd(), file=sys.stderr)",
+        winners: &["semi-global", "needleman-wunsch", "tokens"],
+    },
+    Case {
+        // Aligning the whole context drags in the stray parenthesis.
+        name: "end of a topic deleted, leaving a stray match nearby",
+        before: "import os\n\n",
+        topic: "print(os.getcwd())",
+        after: "",
+        context_width: 10,
+        modified: "import os\n\nprint(os.getcwd  # 1)",
+        expected_offset: 11,
+        expected_topic: "print(os.getcwd",
+        winners: &["local", "smith-waterman", "tokens", "exact"],
+    },
+    Case {
+        // Only global alignment must align the start of the file.
+        name: "first character of a whole-file anchor changed",
+        before: "",
+        topic: "# Settings\nverbose = true\n",
+        after: "",
+        context_width: 10,
+        modified: "; Settings\nverbose = true\n",
+        expected_offset: 0,
+        expected_topic: "; Settings\nverbose = true\n",
+        winners: &["needleman-wunsch"],
+    },
+    Case {
+        // Character alignment is pulled towards a similar line that still
+        // uses the old names.
+        name: "variables renamed",
+        before: "def half_perimeter(a, b):\n    return a + b\n\ndef area(a, b):\n",
+        topic: "    return a * b\n",
+        after: "",
+        context_width: 4,
+        modified: "def half_perimeter(a, b):\n    return a + b\n\ndef area(length, breadth):\n    return length * breadth\n",
+        expected_offset: 71,
+        expected_topic: "    return length * breadth\n",
+        winners: &["tokens"],
+    },
+];
+
+fn run_case(case: &Case) -> Vec<&'static str> {
+    let dir = tempdir::TempDir::new("spor").unwrap();
+    let path = dir.path().join("source.py");
+    let text = format!("{}{}{}", case.before, case.topic, case.after);
+    let offset = case.before.chars().count();
+    let width = case.topic.chars().count();
+
+    let context = Context::new(&text, offset, width, case.context_width).unwrap();
+    let anchor = Anchor::new(&path, context, serde_yaml::Value::Null, "utf-8".to_string()).unwrap();
+    fs::write(&path, case.modified).unwrap();
+
+    // Search the whole file, so that the algorithms are compared directly.
+    let options = UpdateOptions {
+        window_scales: vec![],
+        line_diff: false,
+        ..UpdateOptions::default()
+    };
+
+    ALGORITHMS
+        .iter()
+        .filter(
+            |(_, align, _)| match update_with_options(&anchor, align, &options) {
+                Ok(updated) => {
+                    updated.context().offset() == case.expected_offset
+                        && updated.context().topic() == case.expected_topic
+                }
+                Err(_) => false,
+            },
+        )
+        .map(|(name, _, _)| *name)
+        .collect()
+}
+
+#[test]
+fn corpus() {
+    for case in CORPUS {
+        assert_eq!(run_case(case), case.winners, "{}", case.name);
+    }
+}
+
+#[test]
+fn exact_matches_are_cheapest() {
+    let case = &CORPUS[0];
+    let text = format!("{}{}{}", case.before, case.topic, case.after);
+    let offset = case.before.chars().count();
+    let width = case.topic.chars().count();
+    let context = Context::new(&text, offset, width, case.context_width)
+        .unwrap()
+        .full_text();
+
+    // Count the characters each algorithm compares. When the context is
+    // unchanged, the exact algorithm compares each of them once and never
+    // builds a score matrix.
+    let comparisons: Vec<(&str, usize)> = ALGORITHMS
+        .iter()
+        .map(|(name, align, _)| {
+            let count = Rc::new(Cell::new(0));
+            let counter = count.clone();
+            let counting_score_func = move |a, b| {
+                counter.set(counter.get() + 1);
+                score_func(a, b)
+            };
+            align(&context, case.modified, &counting_score_func, &gap_penalty);
+            (*name, count.get())
+        })
+        .collect();
+
+    let (cheapest, count) = comparisons.iter().min_by_key(|(_, count)| *count).unwrap();
+    assert_eq!(*cheapest, "exact");
+    assert_eq!(*count, context.chars().count());
+}
@@ -8,7 +8,84 @@ Feature: Command line interface
     Given I initialize a repository
     And I create the source file "source.py"
     When I create a new anchor for "source.py" at offset 19
+    Then an anchor for "source.py" at line 2 appears in the listing
+
+  Scenario: Create a new anchor for a whole line
+    Given I initialize a repository
+    And I create the source file "source.py"
+    When I create a new anchor at "source.py":3
     Then an anchor for "source.py" at line 3 appears in the listing
+    And the topic of the anchor for "source.py" is     y = 2
+
+  Scenario: Create a new anchor for a range of lines
+    Given I initialize a repository
+    And I create the source file "source.py"
+    When I create a new anchor at "source.py":2-3
+    Then an anchor for "source.py" at line 2 appears in the listing
+    And the topic of the anchor for "source.py" is     x = 1\n    y = 2
+
+  Scenario: Create a new anchor at a line and column
+    Given I initialize a repository
+    And I create the source file "source.py"
+    When I create a new anchor at "source.py":4:5 with width 5
+    Then an anchor for "source.py" at line 4 appears in the listing
+    And the topic of the anchor for "source.py" is z = 3
+
+  Scenario: Store anchors in a single file
+    Given I initialize a repository with the single-file store
+    And I create the source file "source.py"
+    When I create a new anchor for "source.py" at offset 19
+    Then an anchor for "source.py" at line 2 appears in the listing
+    And the repository is valid
+
+  Scenario: Find anchors by position
+    Given I initialize a repository
+    And I create the source file "source.py"
+    When I create a new anchor for "source.py" at offset 19
+    Then 1 anchor for "source.py" is found at position 2:9
+    And 1 anchor for "source.py" is found at position 21
+    And 0 anchors for "source.py" are found at position 1:1
+
+  Scenario: Anchor files added or removed by hand are noticed
+    Given I initialize a repository
+    And I create the source file "source.py"
+    When I create a new anchor for "source.py" at offset 19
+    And I move the anchor files out of the spor directory
+    Then no anchors for "source.py" appear in the listing
+    When I move the anchor files back into the spor directory
+    Then an anchor for "source.py" at line 2 appears in the listing
+    When I rebuild the anchor index
+    Then an anchor for "source.py" at line 2 appears in the listing
+
+  Scenario: Check a healthy repository
+    Given I initialize a repository
+    And I create the source file "source.py"
+    When I create a new anchor for "source.py" at offset 19
+    Then the repository check passes
+
+  Scenario: Quarantine broken anchor files
+    Given I initialize a repository
+    And I create the source file "source.py"
+    When I create a new anchor for "source.py" at offset 19
+    And I corrupt an anchor file
+    Then the repository check fails
+    When I check the repository with --quarantine
+    Then the repository check passes
+    And an anchor for "source.py" at line 2 appears in the listing
+
+  Scenario: Remove an anchor
+    Given I initialize a repository
+    And I create the source file "source.py"
+    When I create a new anchor for "source.py" at offset 19
+    And I remove the anchor for "source.py"
+    Then no anchors for "source.py" appear in the listing
+
+  Scenario: Dry-run removal keeps the anchor
+    Given I initialize a repository
+    And I create the source file "source.py"
+    When I create a new anchor for "source.py" at offset 19
+    And I remove the anchor for "source.py" with --dry-run
+    Then an anchor for "source.py" at line 2 appears in the listing
 
   Scenario: Validate unchanged source
     Given I initialize a repository
@@ -23,6 +100,111 @@ Feature: Command line interface
     When I modify "source.py"
     Then the repository is invalid
 
+  Scenario: Classify anchors
+    Given I initialize a repository
+    And I create the source file "source.py"
+    And I create the source file "rewritten.py"
+    When I create a new anchor for "source.py" at offset 19
+    And I create a new anchor for "rewritten.py" at offset 19
+    Then the status of the anchor for "source.py" is intact
+    When I modify "source.py"
+    And I rewrite "rewritten.py"
+    Then the status of the anchor for "source.py" is moved
+    And the status of the anchor for "rewritten.py" is lost
+
+  Scenario: Update anchors in modified source
+    Given I initialize a repository
+    And I create the source file "source.py"
+    When I create a new anchor for "source.py" at offset 19
+    And I modify "source.py"
+    Then updating the repository succeeds
+    And an anchor for "source.py" at line 3 appears in the listing
+    And the repository is valid
+
+  Scenario: Preview updates without applying them
+    Given I initialize a repository
+    And I create the source file "source.py"
+    When I create a new anchor for "source.py" at offset 19
+    And I modify "source.py"
+    And I update the repository with --dry-run
+    Then an anchor for "source.py" at line 2 appears in the listing
+
+  Scenario: Unchanged anchors are not rewritten
+    Given I initialize a repository
+    And I create the source file "source.py"
+    When I create a new anchor for "source.py" at offset 19
+    And I add a comment to the anchor files
+    Then updating the repository succeeds
+    And the anchor files still have the comment
+
+  Scenario: Confirm each update
+    Given I initialize a repository
+    And I create the source file "source.py"
+    When I create a new anchor for "source.py" at offset 19
+    And I modify "source.py"
+    And I update the repository answering n
+    Then an anchor for "source.py" at line 2 appears in the listing
+    When I update the repository answering y
+    Then an anchor for "source.py" at line 3 appears in the listing
+
+  Scenario: Anchors in rewritten source are not updated
+    Given I initialize a repository
+    And I create the source file "rewritten.py"
+    And I create the source file "source.py"
+    When I create a new anchor for "rewritten.py" at offset 19
+    And I create a new anchor for "source.py" at offset 19
+    And I rewrite "rewritten.py"
+    And I modify "source.py"
+    Then updating the repository fails
+    And an anchor for "rewritten.py" at line 2 appears in the listing
+    And an anchor for "source.py" at line 3 appears in the listing
+
+  Scenario: Update anchors in one file
+    Given I initialize a repository
+    And I create the source file "other.py"
+    And I create the source file "source.py"
+    When I create a new anchor for "other.py" at offset 19
+    And I create a new anchor for "source.py" at offset 19
+    And I modify "other.py"
+    And I modify "source.py"
+    And I update only "source.py"
+    Then an anchor for "other.py" at line 2 appears in the listing
+    And an anchor for "source.py" at line 3 appears in the listing
+
+  Scenario: Follow renamed source files
+    Given I initialize a repository
+    And I create the source file "source.py"
+    When I create a new anchor for "source.py" at offset 19
+    And I rename "source.py" to "renamed.py"
+    Then updating the repository succeeds
+    And an anchor for "renamed.py" at line 2 appears in the listing
+
+  Scenario: Anchors in deleted source are orphaned
+    Given I initialize a repository
+    And I create the source file "source.py"
+    When I create a new anchor for "source.py" at offset 19
+    And I delete "source.py"
+    Then updating the repository fails
+
+  Scenario: Update anchors in non-ASCII source
+    Given I initialize a repository
+    And I create the non-ASCII source file "source.py"
+    When I create a new anchor for "source.py" at offset 16
+    Then the topic of the anchor for "source.py" is ä = '
+    When I modify "source.py"
+    Then updating the repository succeeds
+    And an anchor for "source.py" at line 3 appears in the listing
+    And the topic of the anchor for "source.py" is ä = '
+
   Scenario: External files are rejected
     Given I initialize a repository
     Then anchoring an external file fails
+
+  Scenario: Set and unset anchor metadata
+    Given I initialize a repository
+    And I create the source file "source.py"
+    When I create a new anchor for "source.py" at offset 19
+    And I set the metadata review.status of the anchor for "source.py" to done
+    Then the metadata of the anchor for "source.py" is {meta: data, review: {status: done}}
+    When I unset the metadata meta of the anchor for "source.py"
+    Then the metadata of the anchor for "source.py" is {review: {status: done}}
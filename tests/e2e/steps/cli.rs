@@ -1,4 +1,5 @@
 extern crate cucumber_rust;
+extern crate serde_yaml;
 
 use std::fs;
 use std::io::Write;
@@ -15,6 +16,15 @@ steps!(World => {
                 .expect("failed to execute spor");
         };
 
+        given regex r"^I initialize a repository with the (.+) store$" (String) |world, store, _step| {
+            let output = Command::new(&world.executable)
+                .arg("init")
+                .arg(format!("--store={}", store))
+                .output()
+                .expect("failed to execute spor");
+            assert!(output.status.success());
+        };
+
         given regex r"^I create the source file (.+)$" (String) |world, filename, _step| {
             let source_file = world.repo_dir.join(filename);
             let code = "def func():
@@ -26,6 +36,16 @@ steps!(World => {
                 .expect("unable to write code to test file");
         };
 
+        given regex r"^I create the non-ASCII source file (.+)$" (String) |world, filename, _step| {
+            let source_file = world.repo_dir.join(filename);
+            let code = "def fünc():
+    ä = 'ö'
+    y = 2
+    return ä + y";
+            fs::write(source_file, code)
+                .expect("unable to write code to test file");
+        };
+
         then regex r"^anchoring an external file fails$" () |world, _step| {
             let source_file = world.external_dir.join("external.py");
             let code = "# nothing";
@@ -62,6 +82,70 @@ steps!(World => {
                 .expect("unable to write code to test file");
         };
 
+        when regex r"^I rewrite (.+)$" (String) |world, filename, _step| {
+            let source_file = world.repo_dir.join(filename);
+            let code = "class Widget:
+    pass";
+            fs::write(source_file, code)
+                .expect("unable to write code to test file");
+        };
+
+        when regex r"^I rename (.+) to (.+)$" (String, String) |world, from, to, _step| {
+            fs::rename(world.repo_dir.join(from), world.repo_dir.join(to))
+                .expect("unable to rename test file");
+        };
+
+        when regex r"^I delete (.+)$" (String) |world, filename, _step| {
+            fs::remove_file(world.repo_dir.join(filename))
+                .expect("unable to delete test file");
+        };
+
+        then regex r"^updating the repository (succeeds|fails)$" (String) |world, result, _step| {
+            let status = Command::new(&world.executable)
+                .arg("update")
+                .status()
+                .expect("failed to execute spor");
+            assert_eq!(status.success(), result == "succeeds");
+        };
+
+        when regex r"^I update only (.+)$" (String) |world, filename, _step| {
+            let status = Command::new(&world.executable)
+                .arg("update")
+                .arg(filename)
+                .status()
+                .expect("failed to execute spor");
+            assert!(status.success());
+        };
+
+        when "I update the repository with --dry-run" |world, _step| {
+            let output = Command::new(&world.executable)
+                .arg("update")
+                .arg("--dry-run")
+                .output()
+                .expect("failed to execute spor");
+            assert!(output.status.success());
+            assert!(String::from_utf8_lossy(&output.stdout).contains(" -> "));
+        };
+
+        when regex r"^I update the repository answering (y|n)$" (String) |world, answer, _step| {
+            let mut cmd = Command::new(&world.executable)
+                .arg("update")
+                .arg("--confirm")
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .spawn()
+                .expect("failed to execute spor");
+
+            {
+                let stdin = cmd.stdin.as_mut()
+                    .expect("Failed to open stdin");
+                stdin.write_all(format!("{}\n", answer).as_bytes())
+                    .expect("Failed to write to stdin");
+            }
+
+            assert!(cmd.wait().expect("failed to wait for spor").success());
+        };
+
         then "a repo data directory exists" |world, _step| {
             assert!(world.repo_dir.join(".spor").exists());
         };
@@ -90,17 +174,243 @@ steps!(World => {
             assert_eq!(String::from_utf8_lossy(&output.stdout), "");
         };
 
-        then regex r"^an anchor for (.+) at line (\d+) appears in the listing$" (String, usize) |world, filename, _lineno, _step| {
+        when regex r"^I create a new anchor at (.+?)(?: with width (\d+))?$" (String, String) |world, location, width, _step| {
+            let mut cmd = Command::new(&world.executable);
+            cmd.arg("add").arg(location);
+            if !width.is_empty() {
+                cmd.arg(width);
+            }
+
+            let mut cmd = cmd
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()
+                .expect("failed to execute spor");
+
+            {
+                let stdin = cmd.stdin.as_mut()
+                    .expect("Failed to open stdin");
+                stdin.write_all("{meta: data}".as_bytes())
+                    .expect("Failed to write to stdin");
+            }
+
+            let output = cmd.wait_with_output()
+                .expect("Failed to read stdout");
+
+            assert!(output.status.success());
+            assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+        };
+
+        then regex r"^an anchor for (.+) at line (\d+) appears in the listing$" (String, usize) |world, filename, lineno, _step| {
             let output = Command::new(&world.executable)
                 .arg("list")
-                .arg(filename)
+                .arg(&filename)
                 .output()
                 .expect("failed to execute spor");
             let output = String::from_utf8_lossy(&output.stdout);
             let output: Vec<&str> = output.split("\n").filter(|s| !s.is_empty()).collect();
             assert_eq!(output.len(), 1);
 
-            // TODO: Look for correct output, e.g. it contains filename, has the right line number, etc.
+            // Listings look like "<id> <path>:<offset> => <metadata>"
+            let location = output[0].split(" => ").next().unwrap();
+            let offset: usize = location.rsplit(':').next().unwrap().parse()
+                .expect("Unable to parse offset from listing");
+
+            let code = fs::read_to_string(world.repo_dir.join(filename))
+                .expect("Unable to read source file");
+            let line = code.chars().take(offset).filter(|c| *c == '\n').count() + 1;
+            assert_eq!(line, lineno);
+        };
+
+        then regex r"^the topic of the anchor for (.+) is (.+)$" (String, String) |world, filename, topic, _step| {
+            let output = Command::new(&world.executable)
+                .arg("list")
+                .arg(filename)
+                .output()
+                .expect("failed to execute spor");
+            let output = String::from_utf8_lossy(&output.stdout);
+            let anchor_id = output.split_whitespace().next()
+                .expect("No anchor in listing");
+
+            let output = Command::new(&world.executable)
+                .arg("details")
+                .arg(anchor_id)
+                .output()
+                .expect("failed to execute spor");
+            let output = String::from_utf8_lossy(&output.stdout);
+            let actual: Vec<&str> = output.lines()
+                .filter(|l| l.starts_with("T> "))
+                .map(|l| &l[3..])
+                .collect();
+            assert_eq!(actual.join("\\n"), topic);
+        };
+
+        when regex r"^I set the metadata (.+) of the anchor for (.+) to (.+)$" (String, String, String) |world, path, filename, value, _step| {
+            let anchor_id = anchor_id_for(world, &filename);
+            let output = Command::new(&world.executable)
+                .arg("set-meta")
+                .arg(anchor_id)
+                .arg(path)
+                .arg(value)
+                .output()
+                .expect("failed to execute spor");
+            assert!(output.status.success());
+        };
+
+        when regex r"^I unset the metadata (.+) of the anchor for (.+)$" (String, String) |world, path, filename, _step| {
+            let anchor_id = anchor_id_for(world, &filename);
+            let output = Command::new(&world.executable)
+                .arg("unset-meta")
+                .arg(anchor_id)
+                .arg(path)
+                .output()
+                .expect("failed to execute spor");
+            assert!(output.status.success());
+        };
+
+        then regex r"^the metadata of the anchor for (.+) is (.+)$" (String, String) |world, filename, expected, _step| {
+            let output = Command::new(&world.executable)
+                .arg("list")
+                .arg(filename)
+                .output()
+                .expect("failed to execute spor");
+            let output = String::from_utf8_lossy(&output.stdout);
+            let (_, metadata) = output.trim_end().split_once(" => ")
+                .expect("No metadata in listing");
+
+            let expected: serde_yaml::Value = serde_yaml::from_str(&expected)
+                .expect("Invalid expected metadata");
+            assert_eq!(metadata, format!("{:?}", expected));
+        };
+
+        when regex r"^I remove the anchor for (.+?)( with --dry-run)?$" (String, String) |world, filename, dry_run, _step| {
+            let output = Command::new(&world.executable)
+                .arg("list")
+                .arg(filename)
+                .output()
+                .expect("failed to execute spor");
+            let output = String::from_utf8_lossy(&output.stdout);
+            let anchor_id = output.split_whitespace().next()
+                .expect("No anchor in listing");
+
+            let mut cmd = Command::new(&world.executable);
+            cmd.arg("remove");
+            if !dry_run.is_empty() {
+                cmd.arg("--dry-run");
+            }
+            let output = cmd.arg(anchor_id)
+                .output()
+                .expect("failed to execute spor");
+            assert!(output.status.success());
+        };
+
+        when "I corrupt an anchor file" |world, _step| {
+            fs::write(world.repo_dir.join(".spor").join("corrupt.yml"), "context: [")
+                .expect("unable to write anchor file");
+        };
+
+        when "I check the repository with --quarantine" |world, _step| {
+            Command::new(&world.executable)
+                .arg("fsck")
+                .arg("--quarantine")
+                .output()
+                .expect("failed to execute spor");
+        };
+
+        then regex r"^the repository check (passes|fails)$" (String) |world, result, _step| {
+            let output = Command::new(&world.executable)
+                .arg("fsck")
+                .output()
+                .expect("failed to execute spor");
+            assert_eq!(output.status.success(), result == "passes");
+        };
+
+        when "I add a comment to the anchor files" |world, _step| {
+            for entry in fs::read_dir(world.repo_dir.join(".spor")).expect("unable to read spor directory") {
+                let path = entry.expect("unable to read spor directory").path();
+                if path.extension().is_some_and(|ext| ext == "yml") {
+                    let data = fs::read_to_string(&path).expect("unable to read anchor file");
+                    fs::write(&path, format!("# Written by hand\n{}", data))
+                        .expect("unable to write anchor file");
+                }
+            }
+        };
+
+        then "the anchor files still have the comment" |world, _step| {
+            for entry in fs::read_dir(world.repo_dir.join(".spor")).expect("unable to read spor directory") {
+                let path = entry.expect("unable to read spor directory").path();
+                if path.extension().is_some_and(|ext| ext == "yml") {
+                    let data = fs::read_to_string(&path).expect("unable to read anchor file");
+                    assert!(data.contains("# Written by hand"));
+                }
+            }
+        };
+
+        when "I move the anchor files out of the spor directory" |world, _step| {
+            let spor_dir = world.repo_dir.join(".spor");
+            let aside = world.repo_dir.join("aside");
+            fs::create_dir(&aside).expect("unable to create directory");
+            for entry in fs::read_dir(&spor_dir).expect("unable to read spor directory") {
+                let path = entry.expect("unable to read spor directory").path();
+                if path.extension().is_some_and(|ext| ext == "yml") {
+                    fs::rename(&path, aside.join(path.file_name().unwrap()))
+                        .expect("unable to move anchor file");
+                }
+            }
+        };
+
+        when "I move the anchor files back into the spor directory" |world, _step| {
+            let spor_dir = world.repo_dir.join(".spor");
+            let aside = world.repo_dir.join("aside");
+            for entry in fs::read_dir(&aside).expect("unable to read directory") {
+                let path = entry.expect("unable to read directory").path();
+                fs::rename(&path, spor_dir.join(path.file_name().unwrap()))
+                    .expect("unable to move anchor file");
+            }
+        };
+
+        when "I rebuild the anchor index" |world, _step| {
+            let output = Command::new(&world.executable)
+                .arg("reindex")
+                .output()
+                .expect("failed to execute spor");
+            assert!(output.status.success());
+        };
+
+        then regex r"^no anchors for (.+) appear in the listing$" (String) |world, filename, _step| {
+            let output = Command::new(&world.executable)
+                .arg("list")
+                .arg(filename)
+                .output()
+                .expect("failed to execute spor");
+            let output = String::from_utf8_lossy(&output.stdout);
+            assert!(output.trim().is_empty());
+        };
+
+        then regex r"^(\d+) anchors? for (.+) (?:is|are) found at position (.+)$" (usize, String, String) |world, count, filename, position, _step| {
+            let output = Command::new(&world.executable)
+                .arg("at")
+                .arg(filename)
+                .arg(position)
+                .output()
+                .expect("failed to execute spor");
+            assert!(output.status.success());
+            let output = String::from_utf8_lossy(&output.stdout);
+            let output: Vec<&str> = output.split("\n").filter(|s| !s.is_empty()).collect();
+            assert_eq!(output.len(), count);
+        };
+
+        then regex r"^the status of the anchor for (.+) is (.+)$" (String, String) |world, filename, expected, _step| {
+            let output = Command::new(&world.executable)
+                .arg("status")
+                .arg("--all")
+                .output()
+                .expect("failed to execute spor");
+            let output = String::from_utf8_lossy(&output.stdout);
+            let id = anchor_id_for(world, &filename);
+            let line = output.lines().find(|line| line.starts_with(&id))
+                .expect("Anchor not in status");
+            assert_eq!(line.split_whitespace().last(), Some(expected.as_str()));
         };
 
         then "the repository is valid" |world, _step| {
@@ -125,3 +435,16 @@ steps!(World => {
 
 
     });
+
+/// The ID of the only anchor for `filename`.
+fn anchor_id_for(world: &World, filename: &str) -> String {
+    let output = Command::new(&world.executable)
+        .arg("list")
+        .arg(filename)
+        .output()
+        .expect("failed to execute spor");
+    let output = String::from_utf8_lossy(&output.stdout);
+    output.split_whitespace().next()
+        .expect("No anchor in listing")
+        .to_string()
+}